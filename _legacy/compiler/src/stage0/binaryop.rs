@@ -6,6 +6,7 @@ pub struct BinaryOp {
     pub rhs: Box<Expr>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[rustfmt::skip]
 pub enum BinaryOpType {
     Add, Sub, Mul, Div, Rem, Shl, Shr, And, Or, Xor,
@@ -1,6 +1,10 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
-use super::{bytecode::Op, ops, Var};
+use super::{
+    bytecode::{self, Op, OpSpan, SpanTable},
+    ops::{self, LiteralValue},
+    SourceSpan, Var,
+};
 
 pub type Label = usize;
 
@@ -37,6 +41,10 @@ pub struct CodeGenerator {
     vars: BTreeMap<Var, u8>,
     dropped: Vec<u8>,
     next_index: u8,
+    cells: BTreeSet<Var>,
+    captures: BTreeMap<Var, u8>,
+    span: SourceSpan,
+    spans: SpanTable,
 }
 
 impl CodeGenerator {
@@ -50,13 +58,32 @@ impl CodeGenerator {
             dropped: Vec::new(),
             // next_index starts at 1, because module ref is at index 0
             next_index: 1,
+            cells: BTreeSet::new(),
+            captures: BTreeMap::new(),
+            span: SourceSpan::default(),
+            spans: SpanTable::new(),
         }
     }
 
     pub fn push(&mut self, op: Op) {
+        self.push_span();
         self.ops.push(op);
     }
 
+    /// sets the source span of the ops pushed from now on, and returns the
+    /// span it replaces, so that it can be set back afterwards
+    pub fn set_span(&mut self, span: SourceSpan) -> SourceSpan {
+        std::mem::replace(&mut self.span, span)
+    }
+
+    fn push_span(&mut self) {
+        let span = OpSpan {
+            start: self.span.start as u32,
+            len: self.span.len as u32,
+        };
+        self.spans.push(self.ops.len(), span);
+    }
+
     fn get_label_data(&mut self, label: Label) -> &mut LabelData {
         match self.labels.get_mut(label) {
             Some(l) => l,
@@ -85,10 +112,15 @@ impl CodeGenerator {
                 jump.get_type().get_name()
             ),
         }
+        self.push_span();
         self.ops.push(jump);
     }
 
     pub fn into_vec(self) -> Vec<Op> {
+        self.into_function().ops
+    }
+
+    pub fn into_function(self) -> bytecode::Function {
         let mut ops = self.ops;
         for label in self.labels {
             let target = label.get_target() as i32;
@@ -102,7 +134,12 @@ impl CodeGenerator {
                 }
             }
         }
-        ops
+        let spans = if self.spans.is_empty() {
+            None
+        } else {
+            Some(self.spans)
+        };
+        bytecode::Function { ops, spans }
     }
 
     // loop methods
@@ -167,12 +204,34 @@ impl CodeGenerator {
         }
     }
 
+    /// makes `var` a captured variable, the next one in the closure's
+    /// captures
+    pub fn add_capture(&mut self, var: Var) {
+        let index = self.captures.len();
+        if index > u8::MAX as usize {
+            panic!("too many captured variables for code generation");
+        }
+        self.captures.insert(var, index as u8);
+    }
+
+    /// makes `var` a variable that's kept in a cell, once it's bound
+    pub fn add_cell(&mut self, var: Var) {
+        self.cells.insert(var);
+    }
+
     pub fn bind_var(&mut self, var: Var) {
         let index = self.get_next_var_index();
         match self.vars.insert(var, index) {
             Some(_) => panic!("variable with id {} has already been bound", var),
             None => {}
         }
+        if self.cells.contains(&var) {
+            // every binding gets a new cell, so closures created in a loop
+            // don't share one
+            self.push(ops::LiteralCreate::new(LiteralValue::None).into());
+            self.push(ops::CellCreate.into());
+            self.push(ops::StackStore::new(index).into());
+        }
     }
 
     pub fn drop_var(&mut self, var: Var) {
@@ -192,12 +251,34 @@ impl CodeGenerator {
     }
 
     pub fn push_var_load(&mut self, var: Var) {
-        let index = self.get_var_index(var);
-        self.push(ops::StackLoad::new(index).into());
+        if self.captures.contains_key(&var) || self.cells.contains(&var) {
+            self.push_var_cell(var);
+            self.push(ops::CellGet.into());
+        } else {
+            let index = self.get_var_index(var);
+            self.push(ops::StackLoad::new(index).into());
+        }
     }
 
     pub fn push_var_store(&mut self, var: Var) {
-        let index = self.get_var_index(var);
-        self.push(ops::StackStore::new(index).into());
+        if self.captures.contains_key(&var) || self.cells.contains(&var) {
+            self.push_var_cell(var);
+            self.push(ops::CellSet.into());
+        } else {
+            let index = self.get_var_index(var);
+            self.push(ops::StackStore::new(index).into());
+        }
+    }
+
+    /// pushes the cell of `var`, which must be captured or kept in a cell
+    pub fn push_var_cell(&mut self, var: Var) {
+        if let Some(index) = self.captures.get(&var) {
+            self.push(ops::CaptureLoad::new(*index).into());
+        } else if self.cells.contains(&var) {
+            let index = self.get_var_index(var);
+            self.push(ops::StackLoad::new(index).into());
+        } else {
+            panic!("variable with id {} isn't kept in a cell", var);
+        }
     }
 }
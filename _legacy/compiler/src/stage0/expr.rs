@@ -2,16 +2,30 @@ use super::{ops, ops::LiteralValue, BinaryOp, CodeGenerator, UnaryOp};
 
 pub type Var = usize;
 
+/// the range of bytes in the source that a node was compiled from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SourceSpan {
+    pub start: usize,
+    pub len: usize,
+}
+
 #[derive(Clone, Copy)]
 pub struct Span<T> {
-    // TODO info for source map
-    pub span: (),
+    pub span: SourceSpan,
     pub inner: T,
 }
 
-pub enum Expr {
-    LiteralValue(Span<LiteralValue>),
-    Var(Span<Var>),
+impl<T> Span<T> {
+    pub fn new(span: SourceSpan, inner: T) -> Span<T> {
+        Span { span, inner }
+    }
+}
+
+pub type Expr = Span<ExprKind>;
+
+pub enum ExprKind {
+    LiteralValue(LiteralValue),
+    Var(Var),
     ModuleRef,
     BinaryOp(BinaryOp),
     UnaryOp(UnaryOp),
@@ -19,6 +33,12 @@ pub enum Expr {
         func: Box<Expr>,
         args: Vec<Expr>,
     },
+    /// a closure of `func`, which captures the cells of `captures`; see
+    /// Function::captures
+    Closure {
+        func: Box<Expr>,
+        captures: Vec<Span<Var>>,
+    },
     SeqIndex {
         seq: Box<Expr>,
         index: Box<Expr>,
@@ -29,12 +49,24 @@ pub enum Expr {
     SeqToList {
         seq: Box<Expr>,
     },
+    /// `seq`, after checking that it has `len` items, or at least `len` if
+    /// `at_least`
+    SeqCheckLen {
+        seq: Box<Expr>,
+        len: u32,
+        at_least: bool,
+    },
+    /// moves the state of a `for` loop, a Tuple, on to the next item, and is
+    /// whether there was one; see the vm's IterNext
+    IterNext(Box<Expr>),
     TupleCreate(Vec<Expr>),
     TupleFromList(Box<Expr>),
     TupleWeakRef(Box<Expr>),
     TupleWeakUpgrade(Box<Expr>),
     TableCreate(Box<Expr>),
     ListCreate(Vec<Expr>),
+    /// the items of `list` from `a` to `b`; a `b` that's none is the end of
+    /// `list`
     ListGetSlice {
         list: Box<Expr>,
         a: Box<Expr>,
@@ -47,17 +79,57 @@ pub enum Expr {
         a: Box<Expr>,
         b: Box<Expr>,
     },
+    /// the items of a List or Buffer, or the text of a Str, from `a` to `b`;
+    /// a `b` that's none is the end of `seq`
+    SeqGetSlice {
+        seq: Box<Expr>,
+        a: Box<Expr>,
+        b: Box<Expr>,
+    },
+    /// the byte offset where `needle` first is in the Str `s`, or none
+    StrFind {
+        s: Box<Expr>,
+        needle: Box<Expr>,
+    },
+    StrFromBuffer(Box<Expr>),
+    /// the text of a value; see the vm's StrFromValue
+    StrFromValue(Box<Expr>),
+    StrToBuffer(Box<Expr>),
+    /// a Map of the (key, value) Tuples in a List
+    MapCreate(Box<Expr>),
+    /// removes the entry of `key` from `map`, and is its value, or none
+    MapRemove {
+        map: Box<Expr>,
+        key: Box<Expr>,
+    },
+    MapContains {
+        map: Box<Expr>,
+        key: Box<Expr>,
+    },
+    MapKeys(Box<Expr>),
+    /// the field of `record` whose name is the Str `field`, looked up when
+    /// it runs. as a place, it's set the same way
+    RecordGet {
+        record: Box<Expr>,
+        field: Box<Expr>,
+    },
 }
 
 impl Expr {
     pub fn compile(&self, g: &mut CodeGenerator) {
-        match self {
-            Expr::LiteralValue(l) => g.push(ops::LiteralCreate::new(l.inner).into()),
-            Expr::Var(var) => g.push_var_load(var.inner),
-            Expr::ModuleRef => g.push(ops::StackLoad::new(0).into()),
-            Expr::BinaryOp(b) => b.compile(g),
-            Expr::UnaryOp(u) => u.compile(g),
-            Expr::Call { func, args } => {
+        let outer = g.set_span(self.span);
+        self.compile_inner(g);
+        g.set_span(outer);
+    }
+
+    fn compile_inner(&self, g: &mut CodeGenerator) {
+        match &self.inner {
+            ExprKind::LiteralValue(l) => g.push(ops::LiteralCreate::new(*l).into()),
+            ExprKind::Var(var) => g.push_var_load(*var),
+            ExprKind::ModuleRef => g.push(ops::StackLoad::new(0).into()),
+            ExprKind::BinaryOp(b) => b.compile(g),
+            ExprKind::UnaryOp(u) => u.compile(g),
+            ExprKind::Call { func, args } => {
                 func.compile(g);
                 assert!(args.len() <= 255);
                 for arg in args {
@@ -65,69 +137,131 @@ impl Expr {
                 }
                 g.push(ops::Call::new(args.len() as u8).into());
             }
-            Expr::SeqIndex { seq, index } => {
+            ExprKind::Closure { func, captures } => {
+                func.compile(g);
+                assert!(captures.len() <= 255);
+                for var in captures {
+                    g.push_var_cell(var.inner);
+                }
+                g.push(ops::ClosureCreate::new(captures.len() as u8).into());
+            }
+            ExprKind::SeqIndex { seq, index } => {
                 seq.compile(g);
                 index.compile(g);
                 g.push(ops::SeqGet.into());
             }
-            Expr::SeqLen { seq } => {
+            ExprKind::SeqLen { seq } => {
                 seq.compile(g);
                 g.push(ops::SeqLen.into());
             }
-            Expr::SeqToList { seq } => {
+            ExprKind::SeqToList { seq } => {
                 seq.compile(g);
                 g.push(ops::SeqToList.into());
             }
-            Expr::TupleCreate(items) => {
+            ExprKind::SeqCheckLen { seq, len, at_least } => {
+                seq.compile(g);
+                g.push(ops::SeqCheckLen::new(*len, *at_least as u8).into());
+            }
+            ExprKind::IterNext(e) => {
+                e.compile(g);
+                g.push(ops::IterNext.into());
+            }
+            ExprKind::TupleCreate(items) => {
                 assert!(items.len() <= 255);
                 for item in items {
                     item.compile(g);
                 }
                 g.push(ops::TupleCreate::new(items.len() as u8).into());
             }
-            Expr::TupleFromList(e) => {
+            ExprKind::TupleFromList(e) => {
                 e.compile(g);
                 g.push(ops::TupleFromList.into());
             }
-            Expr::TupleWeakRef(e) => {
+            ExprKind::TupleWeakRef(e) => {
                 e.compile(g);
                 g.push(ops::TupleWeakRef.into());
             }
-            Expr::TupleWeakUpgrade(e) => {
+            ExprKind::TupleWeakUpgrade(e) => {
                 e.compile(g);
                 g.push(ops::TupleWeakUpgrade.into());
             }
-            Expr::TableCreate(e) => {
+            ExprKind::TableCreate(e) => {
                 e.compile(g);
                 g.push(ops::TableCreate.into());
             }
-            Expr::ListCreate(items) => {
+            ExprKind::ListCreate(items) => {
                 assert!(items.len() <= 255);
                 for item in items {
                     item.compile(g);
                 }
                 g.push(ops::ListCreate::new(items.len() as u8).into());
             }
-            Expr::ListGetSlice { list, a, b } => {
+            ExprKind::ListGetSlice { list, a, b } => {
                 list.compile(g);
                 a.compile(g);
                 b.compile(g);
                 g.push(ops::ListGetSlice.into());
             }
-            Expr::ListPop(e) => {
+            ExprKind::ListPop(e) => {
                 e.compile(g);
                 g.push(ops::ListPop.into());
             }
-            Expr::BufferCreate(e) => {
+            ExprKind::BufferCreate(e) => {
                 e.compile(g);
                 g.push(ops::BufferCreate.into());
             }
-            Expr::BufferGetSlice { buffer, a, b } => {
+            ExprKind::BufferGetSlice { buffer, a, b } => {
                 buffer.compile(g);
                 a.compile(g);
                 b.compile(g);
                 g.push(ops::BufferGetSlice.into());
             }
+            ExprKind::SeqGetSlice { seq, a, b } => {
+                seq.compile(g);
+                a.compile(g);
+                b.compile(g);
+                g.push(ops::SeqGetSlice.into());
+            }
+            ExprKind::StrFind { s, needle } => {
+                s.compile(g);
+                needle.compile(g);
+                g.push(ops::StrFind.into());
+            }
+            ExprKind::StrFromBuffer(e) => {
+                e.compile(g);
+                g.push(ops::StrFromBuffer.into());
+            }
+            ExprKind::StrFromValue(e) => {
+                e.compile(g);
+                g.push(ops::StrFromValue.into());
+            }
+            ExprKind::StrToBuffer(e) => {
+                e.compile(g);
+                g.push(ops::StrToBuffer.into());
+            }
+            ExprKind::MapCreate(e) => {
+                e.compile(g);
+                g.push(ops::MapCreate.into());
+            }
+            ExprKind::MapRemove { map, key } => {
+                map.compile(g);
+                key.compile(g);
+                g.push(ops::MapRemove.into());
+            }
+            ExprKind::MapContains { map, key } => {
+                map.compile(g);
+                key.compile(g);
+                g.push(ops::MapContains.into());
+            }
+            ExprKind::MapKeys(e) => {
+                e.compile(g);
+                g.push(ops::MapKeys.into());
+            }
+            ExprKind::RecordGet { record, field } => {
+                record.compile(g);
+                field.compile(g);
+                g.push(ops::RecordGet.into());
+            }
         }
     }
 
@@ -138,53 +272,81 @@ impl Expr {
     }
 
     fn acc_vars(&self, vars: &mut Vec<Span<Var>>) {
-        match self {
-            Expr::LiteralValue(_) => {}
-            Expr::Var(var) => vars.push(*var),
-            Expr::ModuleRef => {}
-            Expr::BinaryOp(b) => {
+        match &self.inner {
+            ExprKind::LiteralValue(_) => {}
+            ExprKind::Var(var) => vars.push(Span::new(self.span, *var)),
+            ExprKind::ModuleRef => {}
+            ExprKind::BinaryOp(b) => {
                 b.lhs.acc_vars(vars);
                 b.rhs.acc_vars(vars);
             }
-            Expr::UnaryOp(u) => u.expr.acc_vars(vars),
-            Expr::Call { func, args } => {
+            ExprKind::UnaryOp(u) => u.expr.acc_vars(vars),
+            ExprKind::Call { func, args } => {
                 func.acc_vars(vars);
                 for arg in args {
                     arg.acc_vars(vars);
                 }
             }
-            Expr::SeqIndex { seq, index } => {
+            ExprKind::Closure { func, captures } => {
+                func.acc_vars(vars);
+                vars.extend(captures.iter().copied());
+            }
+            ExprKind::SeqIndex { seq, index } => {
                 seq.acc_vars(vars);
                 index.acc_vars(vars);
             }
-            Expr::SeqLen { seq } => seq.acc_vars(vars),
-            Expr::SeqToList { seq } => seq.acc_vars(vars),
-            Expr::TupleCreate(exprs) => {
+            ExprKind::SeqLen { seq } => seq.acc_vars(vars),
+            ExprKind::SeqToList { seq } => seq.acc_vars(vars),
+            ExprKind::SeqCheckLen { seq, .. } => seq.acc_vars(vars),
+            ExprKind::IterNext(e) => e.acc_vars(vars),
+            ExprKind::TupleCreate(exprs) => {
                 for e in exprs {
                     e.acc_vars(vars);
                 }
             }
-            Expr::TupleFromList(e) => e.acc_vars(vars),
-            Expr::TupleWeakRef(e) => e.acc_vars(vars),
-            Expr::TupleWeakUpgrade(e) => e.acc_vars(vars),
-            Expr::TableCreate(e) => e.acc_vars(vars),
-            Expr::ListCreate(exprs) => {
+            ExprKind::TupleFromList(e) => e.acc_vars(vars),
+            ExprKind::TupleWeakRef(e) => e.acc_vars(vars),
+            ExprKind::TupleWeakUpgrade(e) => e.acc_vars(vars),
+            ExprKind::TableCreate(e) => e.acc_vars(vars),
+            ExprKind::ListCreate(exprs) => {
                 for e in exprs {
                     e.acc_vars(vars);
                 }
             }
-            Expr::ListGetSlice { list, a, b } => {
+            ExprKind::ListGetSlice { list, a, b } => {
                 list.acc_vars(vars);
                 a.acc_vars(vars);
                 b.acc_vars(vars);
             }
-            Expr::ListPop(e) => e.acc_vars(vars),
-            Expr::BufferCreate(e) => e.acc_vars(vars),
-            Expr::BufferGetSlice { buffer, a, b } => {
+            ExprKind::ListPop(e) => e.acc_vars(vars),
+            ExprKind::BufferCreate(e) => e.acc_vars(vars),
+            ExprKind::BufferGetSlice { buffer, a, b } => {
                 buffer.acc_vars(vars);
                 a.acc_vars(vars);
                 b.acc_vars(vars);
             }
+            ExprKind::SeqGetSlice { seq, a, b } => {
+                seq.acc_vars(vars);
+                a.acc_vars(vars);
+                b.acc_vars(vars);
+            }
+            ExprKind::StrFind { s, needle } => {
+                s.acc_vars(vars);
+                needle.acc_vars(vars);
+            }
+            ExprKind::StrFromBuffer(e) => e.acc_vars(vars),
+            ExprKind::StrFromValue(e) => e.acc_vars(vars),
+            ExprKind::StrToBuffer(e) => e.acc_vars(vars),
+            ExprKind::MapCreate(e) => e.acc_vars(vars),
+            ExprKind::MapRemove { map, key } | ExprKind::MapContains { map, key } => {
+                map.acc_vars(vars);
+                key.acc_vars(vars);
+            }
+            ExprKind::MapKeys(e) => e.acc_vars(vars),
+            ExprKind::RecordGet { record, field } => {
+                record.acc_vars(vars);
+                field.acc_vars(vars);
+            }
         }
     }
 }
@@ -1,18 +1,25 @@
 use std::collections::BTreeSet;
 
-use super::{bytecode, CodeGenerator, Expr, If, Statement, Var};
+use super::{bytecode, CodeGenerator, Expr, If, Span, Statement, StatementKind, Var};
 
 pub struct Function {
-    pub args: Vec<Var>,
+    pub args: Vec<Span<Var>>,
     pub body: Vec<Statement>,
+    /// variables of enclosing functions that the function uses. they're
+    /// bound before the function runs, as the cells of the closure that
+    /// calls it (see ExprKind::Closure), in this order
+    pub captures: Vec<Var>,
+    /// variables of this function that closures capture. they're kept in
+    /// cells instead of locals, so that the closures share them
+    pub cells: Vec<Var>,
 }
 
 impl Function {
     pub fn compile(mut self) -> bytecode::Function {
         let mut setup = Vec::new();
-        for &arg in &self.args {
-            setup.push(Statement::BindVar(arg));
-            setup.push(Statement::InitVar(arg));
+        for arg in &self.args {
+            setup.push(Span::new(arg.span, StatementKind::BindVar(arg.inner)));
+            setup.push(Span::new(arg.span, StatementKind::InitVar(arg.inner)));
         }
         setup.append(&mut self.body);
         self.body = setup;
@@ -23,14 +30,22 @@ impl Function {
             );
         }
         let mut g = CodeGenerator::new();
+        for var in &self.captures {
+            g.add_capture(*var);
+        }
+        for var in &self.cells {
+            g.add_cell(*var);
+        }
         for statement in &self.body {
             statement.compile(&mut g);
         }
-        bytecode::Function { ops: g.into_vec() }
+        g.into_function()
     }
 
     fn block_scope_analysis(&mut self) -> Result<(), Vec<Var>> {
-        let mut seen = BTreeSet::new();
+        // captured variables belong to another function, so they're never
+        // dropped here
+        let mut seen = self.captures.iter().copied().collect();
         let mut b = BlockScopeAnalysis::new(&mut seen);
         let unknown_scope_vars = b.process_block(&mut self.body);
         if unknown_scope_vars.is_empty() {
@@ -69,9 +84,17 @@ impl<'a> BlockScopeAnalysis<'a> {
             self.process_statement(statement);
         }
         let mut parent_scope = Vec::new();
-        for drop in self.drops.iter().rev() {
+        // the block was processed back to front, so the drops are in
+        // descending order of loc. inserting them in that order leaves the
+        // locs of the ones still to come untouched
+        for drop in self.drops.iter() {
             if self.bindings.contains(&drop.var) {
-                block.insert(drop.loc + 1, Statement::DropVar(drop.var));
+                // the drop is part of the statement where the var was last used
+                let span = block[drop.loc].span;
+                block.insert(
+                    drop.loc + 1,
+                    Span::new(span, StatementKind::DropVar(drop.var)),
+                );
             } else {
                 parent_scope.push(drop.var);
             }
@@ -106,48 +129,48 @@ impl<'a> BlockScopeAnalysis<'a> {
     }
 
     fn process_statement(&mut self, statement: &mut Statement) {
-        match statement {
-            Statement::BindVar(i) => {
+        match &mut statement.inner {
+            StatementKind::BindVar(i) => {
                 self.bindings.insert(*i);
             }
-            Statement::DropVar(_) => {
+            StatementKind::DropVar(_) => {
                 panic!("unexpected DropVar statement during block scope analysis");
             }
-            Statement::InitVar(i) => self.process_var(*i),
-            Statement::Loop(l) => {
+            StatementKind::InitVar(i) => self.process_var(*i),
+            StatementKind::Loop(l) => {
                 if let Some(condition) = l.condition.as_ref() {
                     self.process_expr(condition);
                 }
                 self.process_child_block(&mut l.body);
             }
-            Statement::Break { .. } => {}
-            Statement::Continue { .. } => {}
-            Statement::Expr(expr) => self.process_expr(expr),
-            Statement::Return(expr) => self.process_expr(expr),
-            Statement::IfElse(s) => {
+            StatementKind::Break { .. } => {}
+            StatementKind::Continue { .. } => {}
+            StatementKind::Expr(expr) => self.process_expr(expr),
+            StatementKind::Return(expr) => self.process_expr(expr),
+            StatementKind::IfElse(s) => {
                 self.process_if(&mut s.if_);
                 for if_ in s.else_if.iter_mut() {
                     self.process_if(if_);
                 }
                 self.process_child_block(&mut s.else_);
             }
-            Statement::Assign { place, value } => {
+            StatementKind::Assign { place, value } => {
                 self.process_expr(place);
                 self.process_expr(value);
             }
-            Statement::SeqAppend { seq, src } => {
+            StatementKind::SeqAppend { seq, src } => {
                 self.process_expr(seq);
                 self.process_expr(src);
             }
-            Statement::SeqResize { seq, len } => {
+            StatementKind::SeqResize { seq, len } => {
                 self.process_expr(seq);
                 self.process_expr(len);
             }
-            Statement::ListPush { list, value } => {
+            StatementKind::ListPush { list, value } => {
                 self.process_expr(list);
                 self.process_expr(value);
             }
-            Statement::BufferSetSlice {
+            StatementKind::BufferSetSlice {
                 buffer,
                 src,
                 src_offset,
@@ -163,3 +186,80 @@ impl<'a> BlockScopeAnalysis<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stage0::{ops::LiteralValue, ExprKind, SourceSpan};
+
+    fn statement(kind: StatementKind) -> Statement {
+        Span::new(SourceSpan::default(), kind)
+    }
+
+    fn use_var(var: Var) -> Statement {
+        let expr = Span::new(SourceSpan::default(), ExprKind::Var(var));
+        statement(StatementKind::Expr(expr))
+    }
+
+    #[test]
+    fn drops_go_after_the_last_use() {
+        let mut f = Function {
+            args: Vec::new(),
+            body: vec![
+                statement(StatementKind::BindVar(0)),
+                statement(StatementKind::InitVar(0)),
+                statement(StatementKind::BindVar(1)),
+                statement(StatementKind::InitVar(1)),
+                use_var(0),
+                use_var(1),
+            ],
+            captures: Vec::new(),
+            cells: Vec::new(),
+        };
+        assert!(f.block_scope_analysis().is_ok());
+        let body: Vec<_> = f
+            .body
+            .iter()
+            .map(|s| match &s.inner {
+                StatementKind::BindVar(v) => format!("bind {}", v),
+                StatementKind::InitVar(v) => format!("init {}", v),
+                StatementKind::DropVar(v) => format!("drop {}", v),
+                StatementKind::Expr(_) => "use".to_string(),
+                _ => unreachable!(),
+            })
+            .collect();
+        let expected = [
+            "bind 0", "init 0", "bind 1", "init 1", "use", "drop 0", "use", "drop 1",
+        ];
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn drops_take_the_span_of_the_last_use() {
+        let at = |start| SourceSpan { start, len: 1 };
+        let mut f = Function {
+            args: Vec::new(),
+            body: vec![
+                Span::new(at(0), StatementKind::BindVar(0)),
+                Span::new(at(0), StatementKind::InitVar(0)),
+                Span::new(
+                    at(4),
+                    StatementKind::Expr(Span::new(at(6), ExprKind::Var(0))),
+                ),
+                Span::new(
+                    at(8),
+                    StatementKind::Return(Span::new(
+                        at(15),
+                        ExprKind::LiteralValue(LiteralValue::None),
+                    )),
+                ),
+            ],
+            captures: Vec::new(),
+            cells: Vec::new(),
+        };
+        assert!(f.block_scope_analysis().is_ok());
+        let drop = &f.body[3];
+        assert!(matches!(drop.inner, StatementKind::DropVar(0)));
+        assert_eq!((drop.span.start, drop.span.len), (4, 1));
+    }
+}
@@ -10,8 +10,8 @@ mod unaryop;
 
 pub use binaryop::{BinaryOp, BinaryOpType};
 pub use codegen::{CodeGenerator, Label};
-pub use expr::{Expr, Span, Var};
+pub use expr::{Expr, ExprKind, SourceSpan, Span, Var};
 pub use function::Function;
 pub use module::{Module, ModuleItem, Program};
-pub use statement::{If, IfElse, Loop, Statement};
+pub use statement::{If, IfElse, Loop, Statement, StatementKind};
 pub use unaryop::{UnaryOp, UnaryOpType};
@@ -1,16 +1,51 @@
-use super::{ops::LiteralValue, Function};
+use super::{bytecode, ops::LiteralValue, Function};
 
 pub enum ModuleItem {
     LiteralValue(LiteralValue),
     Buffer(Vec<u8>),
     ModuleRef(u32),
     Function(Function),
+    Str(String),
+    /// the RecordType of a `record`; see the vm's record ops
+    RecordType {
+        name: String,
+        fields: Vec<String>,
+    },
+}
+
+impl ModuleItem {
+    pub fn compile(self) -> bytecode::ModuleItem {
+        match self {
+            ModuleItem::LiteralValue(l) => bytecode::ModuleItem::LiteralValue(l),
+            ModuleItem::Buffer(b) => bytecode::ModuleItem::Buffer(b),
+            ModuleItem::ModuleRef(r) => bytecode::ModuleItem::ModuleRef(r),
+            ModuleItem::Function(f) => bytecode::ModuleItem::Function(f.compile()),
+            ModuleItem::Str(s) => bytecode::ModuleItem::Str(s),
+            ModuleItem::RecordType { name, fields } => {
+                bytecode::ModuleItem::RecordType { name, fields }
+            }
+        }
+    }
 }
 
 pub struct Module {
     pub items: Vec<ModuleItem>,
 }
 
+impl Module {
+    pub fn compile(self) -> bytecode::Module {
+        let items = self.items.into_iter().map(ModuleItem::compile).collect();
+        bytecode::Module { items }
+    }
+}
+
 pub struct Program {
     pub modules: Vec<Module>,
 }
+
+impl Program {
+    pub fn compile(self) -> bytecode::Program {
+        let modules = self.modules.into_iter().map(Module::compile).collect();
+        bytecode::Program { modules }
+    }
+}
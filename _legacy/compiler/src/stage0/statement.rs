@@ -1,6 +1,8 @@
-use super::{ops, CodeGenerator, Expr, Label, Var};
+use super::{ops, CodeGenerator, Expr, ExprKind, Label, Span, Var};
 
-pub enum Statement {
+pub type Statement = Span<StatementKind>;
+
+pub enum StatementKind {
     BindVar(Var),
     DropVar(Var),
     InitVar(Var),
@@ -41,57 +43,69 @@ pub enum Statement {
 
 impl Statement {
     pub fn compile(&self, g: &mut CodeGenerator) {
-        match self {
-            Statement::BindVar(var) => g.bind_var(*var),
-            Statement::DropVar(var) => g.drop_var(*var),
-            Statement::InitVar(var) => g.push_var_store(*var),
-            Statement::Loop(l) => l.compile(g),
-            Statement::Break { label } => {
+        let outer = g.set_span(self.span);
+        self.compile_inner(g);
+        g.set_span(outer);
+    }
+
+    fn compile_inner(&self, g: &mut CodeGenerator) {
+        match &self.inner {
+            StatementKind::BindVar(var) => g.bind_var(*var),
+            StatementKind::DropVar(var) => g.drop_var(*var),
+            StatementKind::InitVar(var) => g.push_var_store(*var),
+            StatementKind::Loop(l) => l.compile(g),
+            StatementKind::Break { label } => {
                 let label = g.loop_get_break(*label);
                 g.push_jump(label, ops::Jump::new(0).into());
             }
-            Statement::Continue { label } => {
+            StatementKind::Continue { label } => {
                 let label = g.loop_get_continue(*label);
                 g.push_jump(label, ops::Jump::new(0).into());
             }
-            Statement::Expr(e) => {
+            StatementKind::Expr(e) => {
                 e.compile(g);
                 g.push(ops::StackPop.into());
             }
-            Statement::Return(e) => {
+            StatementKind::Return(e) => {
                 e.compile(g);
                 g.push(ops::Return.into());
             }
-            Statement::IfElse(s) => s.compile(g),
-            Statement::Assign { place, value } => match &**place {
-                Expr::Var(var) => {
+            StatementKind::IfElse(s) => s.compile(g),
+            StatementKind::Assign { place, value } => match &place.inner {
+                ExprKind::Var(var) => {
                     value.compile(g);
-                    g.push_var_store(var.inner);
+                    g.push_var_store(*var);
                 }
-                Expr::SeqIndex { seq, index } => {
+                ExprKind::SeqIndex { seq, index } => {
                     seq.compile(g);
                     index.compile(g);
                     value.compile(g);
                     g.push(ops::SeqSet.into());
                 }
+                ExprKind::RecordGet { record, field } => {
+                    record.compile(g);
+                    field.compile(g);
+                    value.compile(g);
+                    g.push(ops::RecordSet.into());
+                }
                 _ => panic!("invalid place expression"),
             },
-            Statement::SeqAppend { seq, src } => {
+            StatementKind::SeqAppend { seq, src } => {
                 seq.compile(g);
                 src.compile(g);
                 g.push(ops::SeqAppend.into());
             }
-            Statement::SeqResize { seq, len } => {
+            StatementKind::SeqResize { seq, len } => {
                 seq.compile(g);
                 len.compile(g);
                 g.push(ops::SeqResize.into());
             }
-            Statement::ListPush { list, value } => {
+            StatementKind::ListPush { list, value } => {
                 list.compile(g);
                 value.compile(g);
                 g.push(ops::ListPush.into());
             }
-            Statement::BufferSetSlice {
+            StatementKind::BufferSetSlice {
                 buffer,
                 src,
                 src_offset,
@@ -5,6 +5,7 @@ pub struct UnaryOp {
     pub expr: Box<Expr>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[rustfmt::skip]
 pub enum UnaryOpType {
     Neg, Not, LogicNot, IntToReal, Floor, Ceil, Trunc, Round
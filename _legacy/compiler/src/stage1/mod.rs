@@ -1,3 +1,3 @@
 mod typ;
 
-pub use typ::{Type, FunctionType};
\ No newline at end of file
+pub use typ::{Type, FunctionType, RecordType};
\ No newline at end of file
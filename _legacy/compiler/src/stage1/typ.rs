@@ -12,8 +12,11 @@ pub enum Type {
     Real,
     Tuple(Rc<[Type]>),
     Table,
+    Map,
     List(Box<Type>),
     Buffer,
+    Str,
+    Record(Rc<RecordType>),
     Function(Rc<FunctionType>),
     NativeFn(Rc<FunctionType>),
     Unknown,
@@ -100,3 +103,19 @@ impl FunctionType {
         Some(FunctionType { args, ret })
     }
 }
+
+/// a `record`, which is a Tuple with its RecordType as item 0 and its fields
+/// after that
+#[derive(Clone, PartialEq)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+impl RecordType {
+    /// the index in the record's Tuple of the field called `name`
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        let index = self.fields.iter().position(|f| f == name)?;
+        Some(index + 1)
+    }
+}
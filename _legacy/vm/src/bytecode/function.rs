@@ -1,13 +1,16 @@
-use super::{BytesIO, BytesReadError, Op};
+use super::{BytesIO, BytesReadError, Op, SpanTable};
 
 pub struct Function {
     pub ops: Vec<Op>,
+    pub spans: Option<SpanTable>,
 }
 
+/// only the ops are written; the spans, if any, are written by the Program,
+/// after all of its modules
 impl BytesIO for Function {
     fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
         let (b, ops) = <Vec<Op> as BytesIO>::read(b)?;
-        let f = Function { ops };
+        let f = Function { ops, spans: None };
         Ok((b, f))
     }
     fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
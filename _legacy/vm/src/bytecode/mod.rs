@@ -4,6 +4,7 @@ mod module;
 mod op;
 pub mod ops;
 mod program;
+mod spans;
 
 pub use io::{BytesIO, BytesReadError, DataIO};
 
@@ -12,3 +13,4 @@ pub use op::{Op, OpAction, OpError, OpType, Operation};
 pub use function::Function;
 pub use module::{Module, ModuleItem};
 pub use program::Program;
+pub use spans::{OpSpan, SpanTable};
@@ -1,7 +1,7 @@
 use super::ops::LiteralValue;
 use super::{BytesIO, BytesReadError, Function};
 
-use crate::datamodel::{Buffer, Function as FuncVal, Tuple, Value};
+use crate::datamodel::{Buffer, Function as FuncVal, RecordType, Str, Tuple, Value};
 
 pub struct Module {
     pub items: Vec<ModuleItem>,
@@ -20,7 +20,12 @@ impl Module {
                     refs.push((i, r as usize));
                     Value::None
                 }
-                ModuleItem::Function(f) => FuncVal::new(tuple.clone(), f.ops).into(),
+                ModuleItem::Function(f) => FuncVal::new(tuple.clone(), f.ops, f.spans).into(),
+                ModuleItem::Str(s) => Str::from(s).into(),
+                ModuleItem::RecordType { name, fields } => {
+                    let fields = fields.into_iter().map(Str::from).collect();
+                    RecordType::new(Str::from(name), fields).into()
+                }
             };
             tuple.set(i, val);
         }
@@ -43,6 +48,23 @@ pub enum ModuleItem {
     Buffer(Vec<u8>),
     ModuleRef(u32),
     Function(Function),
+    Str(String),
+    RecordType { name: String, fields: Vec<String> },
+}
+
+fn read_str<'a>(b: &'a [u8]) -> Result<(&'a [u8], String), BytesReadError<'a>> {
+    let (b, n) = <u32 as BytesIO>::read(b)?;
+    let b1 = b.get(n as usize..).ok_or(BytesReadError::EndOfFile)?;
+    let b2 = unsafe { b.get_unchecked(..n as usize) };
+    let s = std::str::from_utf8(b2).or(Err(BytesReadError::InvalidValue(b)))?;
+    Ok((b1, s.to_string()))
+}
+
+fn write_str<'a>(t: &str, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
+    let n = t.len();
+    let b = <u32 as BytesIO>::write(&(n as u32), b)?;
+    b.get_mut(..n)?.copy_from_slice(t.as_bytes());
+    Some(unsafe { b.get_unchecked_mut(n..) })
 }
 
 impl BytesIO for ModuleItem {
@@ -67,6 +89,21 @@ impl BytesIO for ModuleItem {
                 let (b, t) = <Function as BytesIO>::read(b2)?;
                 Ok((b, ModuleItem::Function(t)))
             }
+            4 => {
+                let (b, s) = read_str(b2)?;
+                Ok((b, ModuleItem::Str(s)))
+            }
+            5 => {
+                let (b, name) = read_str(b2)?;
+                let (mut b, n) = <u32 as BytesIO>::read(b)?;
+                let mut fields = Vec::new();
+                for _ in 0..n {
+                    let (rest, field) = read_str(b)?;
+                    fields.push(field);
+                    b = rest;
+                }
+                Ok((b, ModuleItem::RecordType { name, fields }))
+            }
             _ => Err(BytesReadError::InvalidValue(b)),
         }
     }
@@ -91,6 +128,19 @@ impl BytesIO for ModuleItem {
                 let b = <u8 as BytesIO>::write(&3, b)?;
                 <Function as BytesIO>::write(t, b)
             }
+            ModuleItem::Str(t) => {
+                let b = <u8 as BytesIO>::write(&4, b)?;
+                write_str(t, b)
+            }
+            ModuleItem::RecordType { name, fields } => {
+                let b = <u8 as BytesIO>::write(&5, b)?;
+                let b = write_str(name, b)?;
+                let mut b = <u32 as BytesIO>::write(&(fields.len() as u32), b)?;
+                for field in fields {
+                    b = write_str(field, b)?;
+                }
+                Some(b)
+            }
         }
     }
 }
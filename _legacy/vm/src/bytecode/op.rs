@@ -2,7 +2,7 @@ use super::{BytesIO, BytesReadError};
 
 use crate::CallStack;
 
-use crate::datamodel::{Function, NativeFn, Value, ValueTryIntoError, ValueType};
+use crate::datamodel::{Function, NativeFn, Str, Value, ValueTryIntoError, ValueType};
 
 use super::ops::*;
 
@@ -21,10 +21,34 @@ pub enum OpAction {
 pub enum OpError {
     StackEmpty,
     LocalRead(u8),
+    CaptureRead(u8),
     IndexRead(i64),
     IndexWrite(i64),
     IntoType(ValueTryIntoError),
     BadType(ValueType),
+    /// a seq with `found` items, where `len` were expected, or at least
+    /// `len` if `at_least`
+    BadLen {
+        len: u32,
+        at_least: bool,
+        found: usize,
+    },
+    /// a value of type `found` that isn't a seq, where a seq of `len` items,
+    /// or at least `len` if `at_least`, was expected
+    NotASeq {
+        len: u32,
+        at_least: bool,
+        found: ValueType,
+    },
+    /// an index into a Str that isn't on a char boundary
+    CharBoundary(i64),
+    /// a Buffer that isn't valid utf-8, being turned into a Str
+    InvalidUtf8,
+    /// a Map key that is, or has in it, a value of a type that can't be
+    /// hashed; see the datamodel's map.rs
+    Unhashable(ValueType),
+    /// a field that the record's type doesn't have, by name
+    UndefinedField(Str),
 }
 
 impl From<ValueTryIntoError> for OpError {
@@ -132,5 +156,19 @@ create_op_type!(
     // buffer
     BufferCreate, BufferGetSlice, BufferSetSlice,
     // seq
-    SeqLen, SeqResize, SeqGet, SeqSet, SeqToList, SeqAppend
+    SeqLen, SeqResize, SeqGet, SeqSet, SeqToList, SeqAppend,
+    // closure
+    ClosureCreate, CaptureLoad, CellCreate, CellGet, CellSet,
+    // destructuring
+    SeqCheckLen,
+    // iteration
+    IterNext,
+    // slices and str
+    SeqGetSlice, StrFind, StrFromBuffer, StrToBuffer,
+    // map
+    MapCreate, MapRemove, MapContains, MapKeys,
+    // record
+    RecordGet, RecordSet,
+    // string interpolation
+    StrFromValue
 );
@@ -0,0 +1,83 @@
+/*
+A closure is a Function with a tuple of captured variables. Every variable that a closure captures
+lives in a cell, a Tuple with one item, instead of directly in a local, so the function that binds
+it and every closure that captured it share one value. A cell outlives the frame that created it
+for as long as a closure holds it.
+
+The function that binds a captured variable keeps the cell in a local, and reads and writes the
+value with CellGet and CellSet. A closure finds the cell with CaptureLoad.
+*/
+use std::cell::RefCell;
+use std::convert::TryInto;
+
+use crate::datamodel::{Function, Tuple};
+
+use super::{CallStack, OpAction, OpError, Operation};
+
+new_op! {
+    /// pops `captures` cells and a Function, and pushes a closure of the
+    /// function. the cell pushed first is capture 0
+    pub struct ClosureCreate {
+        captures: u8,
+    }
+}
+
+impl Operation for ClosureCreate {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let mut cells = Vec::new();
+        for _ in 0..self.captures {
+            cells.push(m.pop()?);
+        }
+        cells.reverse();
+        let func: Function = m.pop()?.try_into()?;
+        let captures = Tuple::from_iter(cells.into_iter());
+        m.push(func.with_captures(captures).into());
+        Ok(OpAction::None)
+    }
+}
+
+new_op! {
+    /// pushes the cell of captured variable `index`
+    pub struct CaptureLoad {
+        index: u8,
+    }
+}
+
+impl Operation for CaptureLoad {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let cell = m.capture(self.index)?;
+        m.push(cell);
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(CellCreate);
+impl Operation for CellCreate {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let val = m.pop()?;
+        m.push(Tuple::new(vec![RefCell::new(val)]).into());
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(CellGet);
+impl Operation for CellGet {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let cell: Tuple = m.pop()?.try_into()?;
+        let val = cell.get(0).ok_or(OpError::IndexRead(0))?;
+        m.push(val);
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(CellSet);
+impl Operation for CellSet {
+    /// pops the cell, then the value, so that a value that's already on the
+    /// stack can be stored
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let cell: Tuple = m.pop()?.try_into()?;
+        let val = m.pop()?;
+        cell.set(0, val).ok_or(OpError::IndexWrite(0))?;
+        Ok(OpAction::None)
+    }
+}
@@ -2,7 +2,8 @@ use std::cmp::Ordering;
 use std::convert::TryInto;
 
 use crate::datamodel::{
-    Buffer, Function, Identity, List, NativeFn, Table, Unknown, Value, ValueTryIntoError, ValueType,
+    Buffer, Function, Identity, List, Map, NativeFn, RecordType, Str, Table, Unknown, Value,
+    ValueTryIntoError, ValueType,
 };
 
 use super::{CallStack, OpAction, OpError, Operation};
@@ -37,6 +38,15 @@ impl Operation for Cmp {
             Value::Unknown(lhs) => {
                 (lhs.identity() == TryInto::<Unknown>::try_into(rhs)?.identity()).into()
             }
+            Value::Map(lhs) => (lhs.identity() == TryInto::<Map>::try_into(rhs)?.identity()).into(),
+            Value::RecordType(lhs) => {
+                (lhs.identity() == TryInto::<RecordType>::try_into(rhs)?.identity()).into()
+            }
+            // strings compare by their text
+            Value::Str(lhs) => {
+                let rhs: Str = rhs.try_into()?;
+                lhs.as_str().cmp(rhs.as_str()).into()
+            }
         };
         m.push(result);
         Ok(OpAction::None)
@@ -0,0 +1,135 @@
+/*
+A `for` loop keeps its state in a Tuple of three items: the value it iterates, a cursor, and the
+current item. The compiler creates the tuple with the value and two nones, and IterNext moves the
+cursor on and stores the next item, at the top of every iteration.
+
+What the cursor is, and what happens when the value changes during the loop, depends on its type:
+    List, Buffer    the index of the item. the length is read again every iteration, so items
+                    pushed during the loop are visited, and the loop ends early if the value
+                    shrinks past the cursor
+    Tuple           the index of the item; a tuple's length doesn't change
+    Table           the key of the entry. the next entry is the one with the smallest key after
+                    it, so every key is visited once at most: entries inserted after the cursor
+                    are visited, and those removed before they're reached aren't. the item is a
+                    (key, value) Tuple
+    Map             the number of the entry, in insertion order (see datamodel::Map). entries
+                    inserted during the loop are visited, and those removed before they're
+                    reached aren't. the item is a (key, value) Tuple
+    Str             the byte offset of the char; the item is the char, as a Str of its own
+Any other value is an OpError::BadType.
+*/
+use std::convert::TryInto;
+
+use crate::datamodel::{Tuple, Value};
+
+use super::{CallStack, OpAction, OpError, Operation};
+
+static SEQ: usize = 0;
+static CURSOR: usize = 1;
+static ITEM: usize = 2;
+
+// pops the state of a loop, moves it on to the next item, and pushes whether
+// there was one
+new_op_empty!(IterNext);
+impl Operation for IterNext {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let state: Tuple = m.pop()?.try_into()?;
+        let seq = state.get(SEQ).ok_or(OpError::IndexRead(SEQ as i64))?;
+        let cursor = state.get(CURSOR).ok_or(OpError::IndexRead(CURSOR as i64))?;
+        let next = match &seq {
+            Value::Table(t) => {
+                let after = match cursor {
+                    Value::None => None,
+                    cursor => Some(TryInto::<i64>::try_into(cursor)? as u64),
+                };
+                t.next_after(after).map(|(key, val)| {
+                    let key: Value = (key as i64).into();
+                    let item = Tuple::from_iter(vec![key.clone(), val].into_iter());
+                    (key, item.into())
+                })
+            }
+            Value::Map(t) => {
+                let after = match cursor {
+                    Value::None => None,
+                    cursor => Some(TryInto::<i64>::try_into(cursor)? as u64),
+                };
+                t.next_after(after).map(|(order, key, val)| {
+                    let item = Tuple::from_iter(vec![key, val].into_iter());
+                    ((order as i64).into(), item.into())
+                })
+            }
+            Value::Str(t) => {
+                let index = match cursor {
+                    Value::None => 0,
+                    cursor => {
+                        let cursor = TryInto::<i64>::try_into(cursor)? as usize;
+                        let c = t
+                            .char_at(cursor)
+                            .ok_or(OpError::CharBoundary(cursor as i64))?;
+                        cursor + c.len()
+                    }
+                };
+                t.char_at(index).map(|c| ((index as i64).into(), c.into()))
+            }
+            _ => {
+                let index = match cursor {
+                    Value::None => 0,
+                    cursor => TryInto::<i64>::try_into(cursor)? as usize + 1,
+                };
+                let item = match &seq {
+                    Value::Tuple(t) => t.get(index),
+                    Value::List(t) => t.get(index),
+                    Value::Buffer(t) => t.get(index),
+                    _ => return Err(OpError::BadType(seq.get_type())),
+                };
+                item.map(|item| ((index as i64).into(), item))
+            }
+        };
+        let found = next.is_some();
+        if let Some((cursor, item)) = next {
+            state.set(CURSOR, cursor);
+            state.set(ITEM, item);
+        }
+        m.push(found.into());
+        Ok(OpAction::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datamodel::{Key, Map};
+
+    fn int(value: Option<Value>) -> i64 {
+        value
+            .and_then(|v| v.try_into().ok())
+            .expect("expected an Integer")
+    }
+
+    #[test]
+    fn map_loop_visits_every_entry_when_an_insert_compacts() {
+        let map = Map::new((0..10).map(|i| (Key::Integer(i), i.into())).collect());
+        for i in 0..8 {
+            map.remove(&Key::Integer(i));
+        }
+        let state =
+            Tuple::from_iter(vec![map.clone().into(), Value::None, Value::None].into_iter());
+        let mut m = CallStack::new();
+        let mut visited = Vec::new();
+        loop {
+            m.push(state.clone().into());
+            assert!(IterNext.exec(&mut m).is_ok());
+            if int(m.pop().ok()) == 0 {
+                break;
+            }
+            let item: Option<Tuple> = state.get(ITEM).and_then(|v| v.try_into().ok());
+            let key = int(item.and_then(|t| t.get(0)));
+            visited.push(key);
+            // the first insert leaves 8 holes and 2 entries, so it compacts
+            if key < 100 {
+                map.set(Key::Integer(100 + key), key.into());
+            }
+        }
+        assert_eq!(visited, [8, 9, 108, 109]);
+    }
+}
@@ -5,6 +5,8 @@ use crate::datamodel::{List, Value};
 use super::{CallStack, OpAction, OpError, Operation};
 
 new_op! {
+    /// pops `items` values, and pushes a List of them. the value pushed first
+    /// is item 0
     pub struct ListCreate {
         items: u8,
     }
@@ -17,6 +19,7 @@ impl Operation for ListCreate {
             let item = m.pop()?;
             acc.push(item.clone());
         }
+        acc.reverse();
         m.push(List::new(acc).into());
         Ok(OpAction::None)
     }
@@ -48,9 +51,14 @@ impl Operation for ListPop {
 new_op_empty!(ListGetSlice);
 impl Operation for ListGetSlice {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
-        let b: i64 = m.pop()?.try_into()?;
+        let b = m.pop()?;
         let a: i64 = m.pop()?.try_into()?;
         let list: List = m.pop()?.try_into()?;
+        // a none end is the end of the list
+        let b = match b {
+            Value::None => list.len() as i64,
+            b => b.try_into()?,
+        };
         let slice = list
             .get_slice(a as usize, b as usize)
             .ok_or(OpError::IndexRead(b))?;
@@ -58,3 +66,30 @@ impl Operation for ListGetSlice {
         Ok(OpAction::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_create_keeps_the_order_items_were_pushed_in() {
+        let mut m = CallStack::new();
+        for i in 1..=3 {
+            m.push(Value::Integer(i));
+        }
+        assert!(matches!(
+            ListCreate::new(3).exec(&mut m),
+            Ok(OpAction::None)
+        ));
+        let list = match m.pop() {
+            Ok(Value::List(list)) => list,
+            _ => panic!("expected a List"),
+        };
+        let items: Vec<_> = list
+            .as_slice()
+            .iter()
+            .map(|v| v.clone().try_into().ok())
+            .collect();
+        assert_eq!(items, [Some(1i64), Some(2), Some(3)]);
+    }
+}
@@ -0,0 +1,104 @@
+use std::convert::TryInto;
+
+use crate::datamodel::{Key, List, Map, Tuple, Value};
+
+use super::{CallStack, OpAction, OpError, Operation};
+
+/// the key for `value`, or OpError::Unhashable
+pub(super) fn map_key(value: &Value) -> Result<Key, OpError> {
+    Key::new(value).map_err(OpError::Unhashable)
+}
+
+// pops a List of (key, value) Tuples, and pushes a Map of them
+new_op_empty!(MapCreate);
+impl Operation for MapCreate {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let list: List = m.pop()?.try_into()?;
+        let mut entries = Vec::new();
+        for val in list.as_slice().iter() {
+            let tuple: &Tuple = val.try_into()?;
+            let k = map_key(&tuple.get(0).ok_or(OpError::IndexRead(0))?)?;
+            let v = tuple.get(1).ok_or(OpError::IndexRead(1))?;
+            entries.push((k, v));
+        }
+        m.push(Map::new(entries).into());
+        Ok(OpAction::None)
+    }
+}
+
+// pops a key and a Map, removes the key's entry, and pushes its value, or
+// none if there wasn't one
+new_op_empty!(MapRemove);
+impl Operation for MapRemove {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let key = map_key(&m.pop()?)?;
+        let map: Map = m.pop()?.try_into()?;
+        m.push(map.remove(&key).into());
+        Ok(OpAction::None)
+    }
+}
+
+// pops a key and a Map, and pushes whether the Map has an entry for the key
+new_op_empty!(MapContains);
+impl Operation for MapContains {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let key = map_key(&m.pop()?)?;
+        let map: Map = m.pop()?.try_into()?;
+        m.push(map.contains(&key).into());
+        Ok(OpAction::None)
+    }
+}
+
+// pops a Map, and pushes a List of its keys, in insertion order
+new_op_empty!(MapKeys);
+impl Operation for MapKeys {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let map: Map = m.pop()?.try_into()?;
+        m.push(List::new(map.keys()).into());
+        Ok(OpAction::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datamodel::{Str, ValueType};
+
+    fn map() -> Map {
+        Map::new(vec![(Key::Integer(1), Value::Integer(10))])
+    }
+
+    /// runs MapContains for `key` on `map`
+    fn contains(map: &Map, key: Value) -> Result<bool, OpError> {
+        let mut m = CallStack::new();
+        m.push(map.clone().into());
+        m.push(key);
+        MapContains.exec(&mut m)?;
+        Ok(matches!(m.pop()?, Value::Integer(1)))
+    }
+
+    #[test]
+    fn lists_are_unhashable() {
+        let map = map();
+        let list: Value = List::new(vec![Value::Integer(1)]).into();
+        assert!(matches!(
+            contains(&map, list.clone()),
+            Err(OpError::Unhashable(ValueType::List))
+        ));
+        // a tuple is only hashable if all of its items are
+        let tuple = Tuple::from_iter(vec![Value::Integer(1), list].into_iter());
+        assert!(matches!(
+            contains(&map, tuple.into()),
+            Err(OpError::Unhashable(ValueType::List))
+        ));
+    }
+
+    #[test]
+    fn tuples_and_strs_are_keys_by_content() {
+        let map = map();
+        let key = || Tuple::from_iter(vec![Value::Integer(1), Str::new("a").into()].into_iter());
+        map.set(Key::new(&key().into()).ok().unwrap(), Value::None);
+        assert!(matches!(contains(&map, key().into()), Ok(true)));
+        assert!(matches!(contains(&map, Str::new("a").into()), Ok(false)));
+    }
+}
@@ -3,15 +3,20 @@ mod macros;
 
 mod buffer;
 mod call;
+mod closure;
 mod cmp;
 mod int;
+mod iter;
 mod jump;
 mod list;
 mod literal;
+mod map;
 mod num;
 mod real;
+mod record;
 mod seq;
 mod stack;
+mod str;
 mod table;
 mod tuple;
 
@@ -21,14 +26,19 @@ use crate::CallStack;
 
 pub use buffer::{BufferCreate, BufferGetSlice, BufferSetSlice};
 pub use call::{Call, Return};
+pub use closure::{CaptureLoad, CellCreate, CellGet, CellSet, ClosureCreate};
 pub use cmp::{Cmp, GetType};
 pub use int::{And, Not, Or, Shl, Shr, Xor};
+pub use iter::IterNext;
 pub use jump::{Jump, JumpNeg, JumpZero};
 pub use list::{ListCreate, ListGetSlice, ListPop, ListPush};
 pub use literal::{LiteralCreate, LiteralValue};
+pub use map::{MapContains, MapCreate, MapKeys, MapRemove};
 pub use num::{Add, Div, Mul, Neg, Rem, Sub};
 pub use real::{Ceil, Floor, IntToReal, Round, Trunc};
-pub use seq::{SeqAppend, SeqGet, SeqLen, SeqResize, SeqSet, SeqToList};
+pub use record::{RecordGet, RecordSet};
+pub use seq::{SeqAppend, SeqCheckLen, SeqGet, SeqGetSlice, SeqLen, SeqResize, SeqSet, SeqToList};
 pub use stack::{StackCopy, StackLoad, StackPop, StackStore, StackSwap};
+pub use str::{StrFind, StrFromBuffer, StrFromValue, StrToBuffer};
 pub use table::TableCreate;
 pub use tuple::{TupleCreate, TupleFromList, TupleWeakRef, TupleWeakUpgrade};
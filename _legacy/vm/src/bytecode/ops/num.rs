@@ -1,11 +1,11 @@
 use std::convert::TryInto;
 
-use crate::datamodel::{Integer, Real, Value};
+use crate::datamodel::{Integer, Real, Str, Value};
 
 use super::{CallStack, OpAction, OpError, Operation};
 
 macro_rules! impl_math_op {
-    ($name:ident, $e:expr) => {
+    ($name:ident, $e:expr $(, $t:ident => $f:ident)*) => {
         new_op_empty!($name);
         impl Operation for $name {
             fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
@@ -20,6 +20,7 @@ macro_rules! impl_math_op {
                         let rhs: Real = rhs.try_into()?;
                         $e(lhs, rhs).into()
                     }
+                    $(Value::$t(lhs) => $f(lhs, rhs)?,)*
                     _ => return Err(OpError::BadType(lhs.get_type())),
                 };
                 m.push(result);
@@ -29,7 +30,13 @@ macro_rules! impl_math_op {
     };
 }
 
-impl_math_op!(Add, |lhs, rhs| lhs + rhs);
+/// adding strings concatenates them
+fn concat(lhs: Str, rhs: Value) -> Result<Value, OpError> {
+    let rhs: Str = rhs.try_into()?;
+    Ok(lhs.concat(&rhs).into())
+}
+
+impl_math_op!(Add, |lhs, rhs| lhs + rhs, Str => concat);
 impl_math_op!(Sub, |lhs, rhs| lhs - rhs);
 impl_math_op!(Mul, |lhs, rhs| lhs * rhs);
 impl_math_op!(Div, |lhs, rhs| lhs / rhs);
@@ -0,0 +1,81 @@
+/*
+Records are Tuples with their RecordType as item 0 (see the datamodel's record.rs). When the
+compiler knows a record's type, it reads and writes the fields with SeqGet and SeqSet, at the
+index the field has in the type. These ops are for when it doesn't: they look the field up by name,
+in the RecordType that the record has.
+*/
+use std::convert::TryInto;
+
+use crate::datamodel::{RecordType, Str, Tuple};
+
+use super::{CallStack, OpAction, OpError, Operation};
+
+/// the index of the field called `name` in `record`
+fn field_index(record: &Tuple, name: &Str) -> Result<usize, OpError> {
+    let typ: RecordType = record.get(0).ok_or(OpError::IndexRead(0))?.try_into()?;
+    typ.field_index(name.as_str())
+        .ok_or_else(|| OpError::UndefinedField(name.clone()))
+}
+
+// pops a field name and a record, and pushes the field
+new_op_empty!(RecordGet);
+impl Operation for RecordGet {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let name: Str = m.pop()?.try_into()?;
+        let record: Tuple = m.pop()?.try_into()?;
+        let index = field_index(&record, &name)?;
+        let val = record.get(index).ok_or(OpError::IndexRead(index as i64))?;
+        m.push(val);
+        Ok(OpAction::None)
+    }
+}
+
+// pops a value, a field name and a record, and sets the field to the value
+new_op_empty!(RecordSet);
+impl Operation for RecordSet {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let val = m.pop()?;
+        let name: Str = m.pop()?.try_into()?;
+        let record: Tuple = m.pop()?.try_into()?;
+        let index = field_index(&record, &name)?;
+        record
+            .set(index, val)
+            .ok_or(OpError::IndexWrite(index as i64))?;
+        Ok(OpAction::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datamodel::Value;
+
+    /// a record of a type called `name`, with `fields` set to 1, 2, ...
+    fn record(name: &str, fields: &[&str]) -> Value {
+        let fields = fields.iter().map(|f| Str::new(f)).collect();
+        let typ = RecordType::new(Str::new(name), fields);
+        let items = (1..).take(typ.fields().len()).map(Value::Integer);
+        Tuple::from_iter(std::iter::once(typ.into()).chain(items)).into()
+    }
+
+    /// runs RecordGet for the field `name` of `record`
+    fn get(record: &Value, name: &str) -> Result<Value, OpError> {
+        let mut m = CallStack::new();
+        m.push(record.clone());
+        m.push(Str::new(name).into());
+        RecordGet.exec(&mut m)?;
+        m.pop()
+    }
+
+    #[test]
+    fn fields_are_found_in_each_records_own_type() {
+        let point = record("Point", &["x", "y"]);
+        let named = record("Named", &["name", "x"]);
+        assert!(matches!(get(&point, "x"), Ok(Value::Integer(1))));
+        assert!(matches!(get(&named, "x"), Ok(Value::Integer(2))));
+        match get(&point, "name") {
+            Err(OpError::UndefinedField(name)) => assert_eq!(name.as_str(), "name"),
+            _ => panic!("expected an UndefinedField error"),
+        }
+    }
+}
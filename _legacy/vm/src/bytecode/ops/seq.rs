@@ -2,6 +2,7 @@ use std::convert::TryInto;
 
 use crate::datamodel::{Identity, List, Value};
 
+use super::map::map_key;
 use super::{CallStack, OpAction, OpError, Operation};
 
 new_op_empty!(SeqLen);
@@ -13,6 +14,8 @@ impl Operation for SeqLen {
             // Value::Table(t) => t.len(),
             Value::List(t) => t.len(),
             Value::Buffer(t) => t.len(),
+            Value::Str(t) => t.len(),
+            Value::Map(t) => t.len(),
             _ => return Err(OpError::BadType(seq.get_type())),
         };
         m.push((len as i64).into());
@@ -20,6 +23,45 @@ impl Operation for SeqLen {
     }
 }
 
+new_op! {
+    /// pops a seq and pushes it back, if it has `len` items, or at least
+    /// `len` if `at_least` isn't 0. destructuring checks the shape of a value
+    /// with it before taking it apart
+    pub struct SeqCheckLen {
+        len: u32,
+        at_least: u8,
+    }
+}
+
+impl Operation for SeqCheckLen {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let seq = m.pop()?;
+        let at_least = self.at_least != 0;
+        let found = match &seq {
+            Value::Tuple(t) => t.len(),
+            Value::List(t) => t.len(),
+            Value::Buffer(t) => t.len(),
+            _ => {
+                return Err(OpError::NotASeq {
+                    len: self.len,
+                    at_least,
+                    found: seq.get_type(),
+                })
+            }
+        };
+        let len = self.len as usize;
+        if found < len || (found > len && !at_least) {
+            return Err(OpError::BadLen {
+                len: self.len,
+                at_least,
+                found,
+            });
+        }
+        m.push(seq);
+        Ok(OpAction::None)
+    }
+}
+
 new_op_empty!(SeqResize);
 impl Operation for SeqResize {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
@@ -34,12 +76,23 @@ impl Operation for SeqResize {
     }
 }
 
-fn seq_get(seq: &Value, index: i64) -> Result<Value, OpError> {
+/// reads the item at `index`, which is a key for a Map and an Integer for
+/// anything else
+fn seq_get(seq: &Value, index: Value) -> Result<Value, OpError> {
+    if let Value::Map(t) = seq {
+        return Ok(t.get(&map_key(&index)?).unwrap_or(Value::None));
+    }
+    let index: i64 = index.try_into()?;
     match seq {
         Value::Tuple(t) => t.get(index as usize).ok_or(OpError::IndexRead(index)),
         Value::Table(t) => Ok(t.get(index as u64).unwrap_or(Value::None)),
         Value::List(t) => t.get(index as usize).ok_or(OpError::IndexRead(index)),
         Value::Buffer(t) => t.get(index as usize).ok_or(OpError::IndexRead(index)),
+        Value::Str(t) => match t.char_at(index as usize) {
+            Some(c) => Ok(c.into()),
+            None if (index as usize) < t.len() => Err(OpError::CharBoundary(index)),
+            None => Err(OpError::IndexRead(index)),
+        },
         _ => return Err(OpError::BadType(seq.get_type())),
     }
 }
@@ -47,7 +100,7 @@ fn seq_get(seq: &Value, index: i64) -> Result<Value, OpError> {
 new_op_empty!(SeqGet);
 impl Operation for SeqGet {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
-        let index: i64 = m.pop()?.try_into()?;
+        let index = m.pop()?;
         let seq = m.pop()?;
         let val = seq_get(&seq, index)?;
         m.push(val);
@@ -55,7 +108,49 @@ impl Operation for SeqGet {
     }
 }
 
-fn seq_set(seq: &Value, index: i64, val: &Value) -> Result<Value, OpError> {
+new_op_empty!(SeqGetSlice);
+impl Operation for SeqGetSlice {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let b = m.pop()?;
+        let a: i64 = m.pop()?.try_into()?;
+        let seq = m.pop()?;
+        let len = match &seq {
+            Value::List(t) => t.len(),
+            Value::Buffer(t) => t.len(),
+            Value::Str(t) => t.len(),
+            _ => return Err(OpError::BadType(seq.get_type())),
+        };
+        // a none end is the end of the seq
+        let b = match b {
+            Value::None => len,
+            b => TryInto::<i64>::try_into(b)? as usize,
+        };
+        let a = a as usize;
+        let slice: Option<Value> = match &seq {
+            Value::List(t) => t.get_slice(a, b).map(Into::into),
+            Value::Buffer(t) => t.get_slice(a, b).map(Into::into),
+            Value::Str(t) => {
+                if a <= b && b <= t.len() {
+                    let index = if t.is_char_boundary(a) { b } else { a };
+                    let slice = t.get_slice(a, b);
+                    Some(slice.ok_or(OpError::CharBoundary(index as i64))?.into())
+                } else {
+                    None
+                }
+            }
+            _ => return Err(OpError::BadType(seq.get_type())),
+        };
+        m.push(slice.ok_or(OpError::IndexRead(b as i64))?);
+        Ok(OpAction::None)
+    }
+}
+
+/// writes the item at `index`, like seq_get reads it
+fn seq_set(seq: &Value, index: Value, val: &Value) -> Result<Value, OpError> {
+    if let Value::Map(t) = seq {
+        return Ok(t.set(map_key(&index)?, val.clone()).unwrap_or(Value::None));
+    }
+    let index: i64 = index.try_into()?;
     match seq {
         Value::Tuple(t) => t
             .set(index as usize, val.clone())
@@ -75,7 +170,7 @@ new_op_empty!(SeqSet);
 impl Operation for SeqSet {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
         let val = m.pop()?;
-        let index: i64 = m.pop()?.try_into()?;
+        let index = m.pop()?;
         let seq = m.pop()?;
         seq_set(&seq, index, &val)?;
         Ok(OpAction::None)
@@ -86,6 +181,7 @@ fn seq_to_vec(seq: &Value) -> Result<Vec<Value>, OpError> {
     Ok(match seq {
         Value::Tuple(t) => t.iter().collect(),
         Value::Table(t) => t.to_vec(),
+        Value::Map(t) => t.to_vec(),
         Value::List(t) => t.as_slice().to_vec(),
         Value::Buffer(t) => t.as_slice().iter().map(|b| (*b as i64).into()).collect(),
         _ => return Err(OpError::BadType(seq.get_type())),
@@ -132,3 +228,86 @@ impl Operation for SeqAppend {
         Ok(OpAction::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datamodel::{Str, Tuple, ValueType};
+
+    /// runs SeqCheckLen on a tuple of `items` integers
+    fn check_len(items: i64, len: u32, at_least: u8) -> Result<OpAction, OpError> {
+        let mut m = CallStack::new();
+        m.push(Tuple::from_iter((0..items).map(Value::Integer)).into());
+        SeqCheckLen::new(len, at_least).exec(&mut m)
+    }
+
+    /// runs `op` on the Str "hé!" and `args`, and shows what it pushed
+    fn on_str(op: impl Operation, args: &[i64]) -> Result<String, OpError> {
+        let mut m = CallStack::new();
+        m.push(Str::new("hé!").into());
+        for arg in args {
+            m.push(Value::Integer(*arg));
+        }
+        op.exec(&mut m)?;
+        Ok(format!("{:?}", m.pop()?))
+    }
+
+    #[test]
+    fn check_len_reports_the_arity_it_wanted() {
+        assert!(check_len(2, 2, 0).is_ok());
+        assert!(check_len(3, 2, 1).is_ok());
+        assert!(matches!(
+            check_len(3, 2, 0),
+            Err(OpError::BadLen {
+                len: 2,
+                at_least: false,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            check_len(1, 2, 1),
+            Err(OpError::BadLen {
+                len: 2,
+                at_least: true,
+                found: 1
+            })
+        ));
+        let mut m = CallStack::new();
+        m.push(Value::Integer(1));
+        assert!(matches!(
+            SeqCheckLen::new(2, 1).exec(&mut m),
+            Err(OpError::NotASeq {
+                len: 2,
+                at_least: true,
+                found: ValueType::Integer
+            })
+        ));
+    }
+
+    #[test]
+    fn strs_are_indexed_by_char_boundaries() {
+        assert_eq!(on_str(SeqGet, &[1]).ok().as_deref(), Some(r#""é""#));
+        assert!(matches!(
+            on_str(SeqGet, &[2]),
+            Err(OpError::CharBoundary(2))
+        ));
+        assert!(matches!(on_str(SeqGet, &[4]), Err(OpError::IndexRead(4))));
+        assert_eq!(
+            on_str(SeqGetSlice, &[1, 4]).ok().as_deref(),
+            Some(r#""é!""#)
+        );
+        // the error is at whichever bound isn't on a char boundary
+        assert!(matches!(
+            on_str(SeqGetSlice, &[0, 2]),
+            Err(OpError::CharBoundary(2))
+        ));
+        assert!(matches!(
+            on_str(SeqGetSlice, &[2, 4]),
+            Err(OpError::CharBoundary(2))
+        ));
+        assert!(matches!(
+            on_str(SeqGetSlice, &[3, 5]),
+            Err(OpError::IndexRead(5))
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+use std::convert::TryInto;
+
+use crate::datamodel::{Buffer, Str, Value};
+
+use super::{CallStack, OpAction, OpError, Operation};
+
+// pops a needle and a Str, and pushes the byte offset of the first place in
+// the Str where the needle is, or none
+new_op_empty!(StrFind);
+impl Operation for StrFind {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let needle: Str = m.pop()?.try_into()?;
+        let s: Str = m.pop()?.try_into()?;
+        m.push(s.find(&needle).map(|i| i as i64).into());
+        Ok(OpAction::None)
+    }
+}
+
+// pops a Buffer and pushes a Str with a copy of its bytes, which must be
+// valid utf-8
+new_op_empty!(StrFromBuffer);
+impl Operation for StrFromBuffer {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let buffer: Buffer = m.pop()?.try_into()?;
+        let text = String::from_utf8(buffer.as_slice().to_vec()).or(Err(OpError::InvalidUtf8))?;
+        m.push(Str::from(text).into());
+        Ok(OpAction::None)
+    }
+}
+
+// pops a Str and pushes a new Buffer with its bytes
+new_op_empty!(StrToBuffer);
+impl Operation for StrToBuffer {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let s: Str = m.pop()?.try_into()?;
+        m.push(Buffer::new(s.as_str().as_bytes().to_vec()).into());
+        Ok(OpAction::None)
+    }
+}
+
+// pops a value and pushes its text: a Str as it is, and anything else as its
+// debug output (see the datamodel's debug.rs), so 1.5 is `1.5` and a List of
+// a Str is `["a"]`
+new_op_empty!(StrFromValue);
+impl Operation for StrFromValue {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let text = match m.pop()? {
+            Value::Str(s) => s,
+            value => Str::from(format!("{:?}", value)),
+        };
+        m.push(text.into());
+        Ok(OpAction::None)
+    }
+}
@@ -6,6 +6,8 @@ use crate::datamodel::{List, Tuple, TupleWeak};
 use super::{CallStack, OpAction, OpError, Operation};
 
 new_op! {
+    /// pops `items` values, and pushes a Tuple of them. the value pushed first
+    /// is item 0
     pub struct TupleCreate {
         items: u8,
     }
@@ -18,6 +20,7 @@ impl Operation for TupleCreate {
             let item = m.pop()?;
             acc.push(RefCell::new(item.clone()));
         }
+        acc.reverse();
         m.push(Tuple::new(acc).into());
         Ok(OpAction::None)
     }
@@ -52,3 +55,27 @@ impl Operation for TupleWeakUpgrade {
         Ok(OpAction::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datamodel::Value;
+
+    #[test]
+    fn tuple_create_keeps_the_order_items_were_pushed_in() {
+        let mut m = CallStack::new();
+        for i in 1..=3 {
+            m.push(Value::Integer(i));
+        }
+        assert!(matches!(
+            TupleCreate::new(3).exec(&mut m),
+            Ok(OpAction::None)
+        ));
+        let tuple = match m.pop() {
+            Ok(Value::Tuple(tuple)) => tuple,
+            _ => panic!("expected a Tuple"),
+        };
+        let items: Vec<_> = tuple.iter().map(|v| v.try_into().ok()).collect();
+        assert_eq!(items, [Some(1i64), Some(2), Some(3)]);
+    }
+}
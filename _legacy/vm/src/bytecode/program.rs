@@ -1,4 +1,13 @@
-use super::{BytesIO, BytesReadError, Module};
+/*
+A program is written as its list of modules, followed by an optional section with the span tables
+of its functions. Readers from before span tables stop after the modules and never look at the
+section, so a program with spans can still be loaded by them, just without its spans.
+
+The section starts with its length in bytes, so a reader that doesn't know what's in it can skip
+it, and then has a (module index, item index, SpanTable) entry for each function that has spans.
+It's only written when there are spans, so a program without them is written exactly as before.
+*/
+use super::{BytesIO, BytesReadError, Module, ModuleItem, SpanTable};
 
 use crate::datamodel::{Tuple, Value};
 
@@ -25,12 +34,122 @@ impl Program {
     }
 }
 
+/// an entry of the spans section: the module and item of a function, and its
+/// span table
+type SpansEntry = (u32, u32, SpanTable);
+
 impl BytesIO for Program {
     fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
         let (b, modules) = <Vec<Module> as BytesIO>::read(b)?;
-        Ok((b, Program { modules }))
+        let mut program = Program { modules };
+        if b.is_empty() {
+            return Ok((b, program));
+        }
+        let (b, len) = <u32 as BytesIO>::read(b)?;
+        let section = b.get(..len as usize).ok_or(BytesReadError::EndOfFile)?;
+        let (_, entries) = <Vec<SpansEntry> as BytesIO>::read(section)?;
+        for (module, item, spans) in entries {
+            let item = program
+                .modules
+                .get_mut(module as usize)
+                .and_then(|m| m.items.get_mut(item as usize));
+            match item {
+                Some(ModuleItem::Function(f)) => f.spans = Some(spans),
+                _ => return Err(BytesReadError::InvalidValue(section)),
+            }
+        }
+        Ok((&b[len as usize..], program))
     }
     fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
-        <Vec<Module> as BytesIO>::write(&t.modules, b)
+        let b = <Vec<Module> as BytesIO>::write(&t.modules, b)?;
+        let mut entries = Vec::new();
+        for (i, module) in t.modules.iter().enumerate() {
+            for (j, item) in module.items.iter().enumerate() {
+                if let ModuleItem::Function(f) = item {
+                    if let Some(spans) = &f.spans {
+                        entries.push((i as u32, j as u32, spans.clone()));
+                    }
+                }
+            }
+        }
+        if entries.is_empty() {
+            return Some(b);
+        }
+        // the length goes first, but is only known once the entries are written
+        if b.len() < 4 {
+            return None;
+        }
+        let (len, section) = b.split_at_mut(4);
+        let available = section.len();
+        let rest = <Vec<SpansEntry> as BytesIO>::write(&entries, section)?;
+        <u32 as BytesIO>::write(&((available - rest.len()) as u32), len)?;
+        Some(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::ops::Jump;
+    use crate::bytecode::{Function, OpSpan};
+
+    fn to_bytes(program: &Program) -> Vec<u8> {
+        let mut b = vec![0; 1024];
+        let rest = <Program as BytesIO>::write(program, &mut b).unwrap().len();
+        b.truncate(1024 - rest);
+        b
+    }
+
+    /// a program with one module of a Buffer and a function
+    fn program(spans: Option<SpanTable>) -> Program {
+        let ops = vec![Jump::new(1).into(), Jump::new(-1).into()];
+        let items = vec![
+            ModuleItem::Buffer(b"x".to_vec()),
+            ModuleItem::Function(Function { ops, spans }),
+        ];
+        Program {
+            modules: vec![Module { items }],
+        }
+    }
+
+    fn spans() -> SpanTable {
+        let mut spans = SpanTable::new();
+        spans.push(0, OpSpan { start: 3, len: 4 });
+        spans.push(1, OpSpan { start: 9, len: 2 });
+        spans
+    }
+
+    #[test]
+    fn spans_round_trip() {
+        let b = to_bytes(&program(Some(spans())));
+        let read = match <Program as BytesIO>::read(&b) {
+            Ok(([], read)) => read,
+            _ => panic!("couldn't read the program back"),
+        };
+        match read.modules[0].items.as_slice() {
+            [ModuleItem::Buffer(s), ModuleItem::Function(f)] => {
+                assert_eq!(s, b"x");
+                assert_eq!(f.ops.len(), 2);
+                assert_eq!(f.spans, Some(spans()));
+            }
+            _ => panic!("expected a Buffer and a Function"),
+        }
+        assert_eq!(to_bytes(&read), b);
+    }
+
+    #[test]
+    fn old_readers_skip_the_spans() {
+        let without = to_bytes(&program(None));
+        let with = to_bytes(&program(Some(spans())));
+        // the modules are written the same either way, and a reader from
+        // before span tables reads just the modules
+        assert_eq!(with[..without.len()], without[..]);
+        match <Vec<Module> as BytesIO>::read(&with) {
+            Ok((rest, modules)) => {
+                assert_eq!(modules.len(), 1);
+                assert_eq!(rest, &with[without.len()..]);
+            }
+            _ => panic!("couldn't read the modules"),
+        }
     }
 }
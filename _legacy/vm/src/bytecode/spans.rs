@@ -0,0 +1,95 @@
+/*
+A SpanTable maps the ops of a function back to the range of source bytes they were compiled from,
+so that an OpError can be reported at the code that caused it.
+
+The table is a list of (op index, span) entries, sorted by op index. Each entry covers its op and
+every op after it, up to the next entry. Most source constructs compile to a run of ops, and a run
+only needs one entry, which keeps the table small.
+*/
+use super::{BytesIO, BytesReadError};
+
+/// a range of bytes in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpSpan {
+    pub start: u32,
+    pub len: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpanTable {
+    entries: Vec<(u32, OpSpan)>,
+}
+
+impl SpanTable {
+    pub fn new() -> SpanTable {
+        SpanTable {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// records that the ops from index `op` onwards come from `span`. ops
+    /// must be pushed in order
+    pub fn push(&mut self, op: usize, span: OpSpan) {
+        let op = op as u32;
+        match self.entries.last_mut() {
+            Some((_, last)) if *last == span => {}
+            // nothing was emitted for the last span, so it can be replaced
+            Some((last_op, last)) if *last_op == op => *last = span,
+            _ => self.entries.push((op, span)),
+        }
+    }
+
+    /// the span of the op at index `op`
+    pub fn lookup(&self, op: usize) -> Option<OpSpan> {
+        let i = self.entries.partition_point(|(o, _)| *o as usize <= op);
+        let (_, span) = self.entries.get(i.checked_sub(1)?)?;
+        Some(*span)
+    }
+}
+
+impl BytesIO for SpanTable {
+    fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
+        let (b, entries) = <Vec<(u32, u32, u32)> as BytesIO>::read(b)?;
+        let entries = entries
+            .into_iter()
+            .map(|(op, start, len)| (op, OpSpan { start, len }))
+            .collect();
+        Ok((b, SpanTable { entries }))
+    }
+    fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        let mut b = <u32 as BytesIO>::write(&(t.entries.len() as u32), b)?;
+        for (op, span) in &t.entries {
+            b = <(u32, u32, u32) as BytesIO>::write(&(*op, span.start, span.len), b)?;
+        }
+        Some(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: u32) -> OpSpan {
+        OpSpan { start, len: 1 }
+    }
+
+    #[test]
+    fn entries_cover_the_ops_up_to_the_next() {
+        let mut t = SpanTable::new();
+        t.push(0, span(10));
+        // nothing was emitted for span 20, so span 30 replaces it
+        t.push(2, span(20));
+        t.push(2, span(30));
+        t.push(3, span(30));
+        t.push(5, span(40));
+        assert_eq!(t.entries.len(), 3);
+        let spans: Vec<_> = (0..7).map(|op| t.lookup(op).map(|s| s.start)).collect();
+        let expected = [10, 10, 30, 30, 30, 40, 40].map(Some);
+        assert_eq!(spans, expected);
+        assert_eq!(SpanTable::new().lookup(0), None);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::bytecode::{OpAction, OpError, Operation};
+use crate::bytecode::{OpAction, OpError, OpSpan, Operation};
 use crate::datamodel::{Function, Value};
 
 use super::CallStack;
@@ -14,6 +14,7 @@ impl CallFrame {
     pub fn new(function: Function) -> CallFrame {
         let mut stack = CallStack::new();
         stack.store(0, function.module.clone().into());
+        stack.set_captures(function.captures.clone());
         CallFrame {
             parent: None,
             function,
@@ -26,8 +27,15 @@ impl CallFrame {
         self.stack.push(val);
     }
 
+    /// jumps `index` ops from the op that ran last, which is the jump
     pub fn jump(&mut self, index: i32) {
-        self.cursor = (self.cursor as isize + index as isize) as usize;
+        self.cursor = (self.cursor as isize - 1 + index as isize) as usize;
+    }
+
+    /// the source span of the op that ran last; for a caller's frame, that's
+    /// the call
+    pub fn span(&self) -> Option<OpSpan> {
+        self.function.span_of(self.cursor.checked_sub(1)?)
     }
 
     pub fn exec(&mut self) -> Result<OpAction, OpError> {
@@ -39,3 +47,33 @@ impl CallFrame {
         op.exec(&mut self.stack)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::ops::Jump;
+    use crate::datamodel::Tuple;
+
+    fn run_jump(frame: &mut CallFrame) {
+        match frame.exec() {
+            Ok(OpAction::Jump(dest)) => frame.jump(dest),
+            _ => panic!("expected a jump"),
+        }
+    }
+
+    #[test]
+    fn jump_is_relative_to_the_jump_op() {
+        // dests are computed by the compiler as the target's index minus the
+        // jump's index; see CodeGenerator::into_function
+        let ops = vec![
+            Jump::new(2).into(),
+            Jump::new(-1).into(),
+            Jump::new(-1).into(),
+        ];
+        let mut frame = CallFrame::new(Function::new(Tuple::empty(0), ops, None));
+        run_jump(&mut frame);
+        assert_eq!(frame.cursor, 2);
+        run_jump(&mut frame);
+        assert_eq!(frame.cursor, 1);
+    }
+}
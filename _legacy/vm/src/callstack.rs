@@ -1,11 +1,12 @@
 use std::mem::swap;
 
 use crate::bytecode::OpError;
-use crate::datamodel::Value;
+use crate::datamodel::{Tuple, Value};
 
 pub struct CallStack {
     stack: Vec<Value>,
     locals: Vec<Value>,
+    captures: Option<Tuple>,
 }
 
 impl CallStack {
@@ -13,9 +14,22 @@ impl CallStack {
         CallStack {
             stack: Vec::new(),
             locals: Vec::new(),
+            captures: None,
         }
     }
 
+    pub fn set_captures(&mut self, captures: Option<Tuple>) {
+        self.captures = captures;
+    }
+
+    /// the cell of captured variable `index`
+    pub fn capture(&self, index: u8) -> Result<Value, OpError> {
+        self.captures
+            .as_ref()
+            .and_then(|c| c.get(index as usize))
+            .ok_or(OpError::CaptureRead(index))
+    }
+
     pub fn load(&self, index: u8) -> Result<&Value, OpError> {
         self.locals
             .get(index as usize)
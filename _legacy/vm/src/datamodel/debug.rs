@@ -0,0 +1,112 @@
+/*
+Debug output for Values. Numbers, strings and none print like literals, and containers print their
+items: Tuples as `(a, b)`, Lists as `[a, b]`, Maps as `{k: v}` and Tables as `table {k: v}`. A
+record prints with the names of its type and fields, like `Point { x: 1, y: 2 }`. Functions and
+other values that have no text of their own print as their type, in angle brackets.
+
+A container that's inside itself would print forever, so a container that's already being printed
+further out prints as `...` instead.
+*/
+use std::convert::TryInto;
+use std::fmt;
+
+use super::{Identity, RecordType, Tuple, Value};
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self, &mut Vec::new())
+    }
+}
+
+/// writes `value`. `open` has the identities of the containers around it
+fn write_value(f: &mut fmt::Formatter, value: &Value, open: &mut Vec<usize>) -> fmt::Result {
+    let identity = match value {
+        Value::None => return write!(f, "none"),
+        Value::Integer(v) => return write!(f, "{}", v),
+        Value::Real(v) => return write!(f, "{:?}", v),
+        Value::Str(v) => return write!(f, "{:?}", v.as_str()),
+        Value::Buffer(v) => return write!(f, "b\"{}\"", v.as_slice().escape_ascii()),
+        Value::RecordType(t) => return write!(f, "<record {}>", t.name().as_str()),
+        Value::Tuple(t) => t.identity(),
+        Value::List(t) => t.identity(),
+        Value::Table(t) => t.identity(),
+        Value::Map(t) => t.identity(),
+        _ => return write!(f, "<{}>", value.get_type().as_str()),
+    };
+    if open.contains(&identity) {
+        return write!(f, "...");
+    }
+    open.push(identity);
+    let result = match value {
+        Value::Tuple(t) => write_tuple(f, t, open),
+        Value::List(t) => write_items(f, "[", &t.as_slice(), "]", open),
+        Value::Table(t) => {
+            write!(f, "table ")?;
+            write_entries(f, &t.to_vec(), open)
+        }
+        Value::Map(t) => write_entries(f, &t.to_vec(), open),
+        _ => unreachable!("only containers have an identity here"),
+    };
+    open.pop();
+    result
+}
+
+fn write_tuple(f: &mut fmt::Formatter, t: &Tuple, open: &mut Vec<usize>) -> fmt::Result {
+    let items: Vec<Value> = t.iter().collect();
+    let typ: Option<RecordType> = items.first().and_then(|v| v.clone().try_into().ok());
+    let typ = match typ {
+        Some(typ) => typ,
+        None if items.len() == 1 => {
+            write!(f, "(")?;
+            write_value(f, &items[0], open)?;
+            return write!(f, ",)");
+        }
+        None => return write_items(f, "(", &items, ")", open),
+    };
+    write!(f, "{} {{", typ.name().as_str())?;
+    for (i, field) in typ.fields().iter().enumerate() {
+        let sep = if i == 0 { " " } else { ", " };
+        write!(f, "{}{}: ", sep, field.as_str())?;
+        match items.get(i + 1) {
+            Some(item) => write_value(f, item, open)?,
+            None => write!(f, "none")?,
+        }
+    }
+    if typ.fields().is_empty() {
+        write!(f, "}}")
+    } else {
+        write!(f, " }}")
+    }
+}
+
+fn write_items(
+    f: &mut fmt::Formatter,
+    start: &str,
+    items: &[Value],
+    end: &str,
+    open: &mut Vec<usize>,
+) -> fmt::Result {
+    write!(f, "{}", start)?;
+    for (i, item) in items.iter().enumerate() {
+        if i != 0 {
+            write!(f, ", ")?;
+        }
+        write_value(f, item, open)?;
+    }
+    write!(f, "{}", end)
+}
+
+/// writes (key, value) Tuples as `{k: v, ...}`
+fn write_entries(f: &mut fmt::Formatter, entries: &[Value], open: &mut Vec<usize>) -> fmt::Result {
+    write!(f, "{{")?;
+    for (i, entry) in entries.iter().enumerate() {
+        if i != 0 {
+            write!(f, ", ")?;
+        }
+        let entry: &Tuple = entry.try_into().map_err(|_| fmt::Error)?;
+        write_value(f, &entry.get(0).unwrap_or(Value::None), open)?;
+        write!(f, ": ")?;
+        write_value(f, &entry.get(1).unwrap_or(Value::None), open)?;
+    }
+    write!(f, "}}")
+}
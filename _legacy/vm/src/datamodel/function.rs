@@ -1,6 +1,6 @@
 use std::rc::Rc;
 
-use crate::bytecode::Op;
+use crate::bytecode::{Op, OpSpan, SpanTable};
 
 use super::{Identity, Tuple};
 
@@ -8,19 +8,43 @@ use super::{Identity, Tuple};
 pub struct Function {
     pub module: Tuple,
     pub ops: Rc<[Op]>,
+    pub spans: Option<Rc<SpanTable>>,
+    /// the cells of the variables a closure captured, in the order that
+    /// CaptureLoad indexes them
+    pub captures: Option<Tuple>,
 }
 
 impl Function {
-    pub fn new(module: Tuple, ops: Vec<Op>) -> Function {
+    pub fn new(module: Tuple, ops: Vec<Op>, spans: Option<SpanTable>) -> Function {
         Function {
             module,
             ops: Rc::from(ops),
+            spans: spans.map(Rc::new),
+            captures: None,
         }
     }
+
+    /// a closure of this function, which shares its ops and module
+    pub fn with_captures(&self, captures: Tuple) -> Function {
+        Function {
+            captures: Some(captures),
+            ..self.clone()
+        }
+    }
+
+    /// the source span of the op at index `op`, if the function has spans
+    pub fn span_of(&self, op: usize) -> Option<OpSpan> {
+        self.spans.as_ref()?.lookup(op)
+    }
 }
 
+/// closures of one function share its ops, so each is told apart by its own
+/// tuple of captures
 impl Identity for Function {
     fn identity(&self) -> usize {
-        Rc::as_ptr(&self.ops).cast::<Op>() as usize
+        match &self.captures {
+            Some(captures) => captures.identity(),
+            None => Rc::as_ptr(&self.ops).cast::<Op>() as usize,
+        }
     }
 }
@@ -0,0 +1,205 @@
+/*
+A Map is a hash map from keys to values. Unlike a Table, its keys can be any hashable Value:
+    Integer     by value
+    Real        by bit pattern, so 0.0 and -0.0 are different keys, and a NaN is equal to itself
+    Str         by text
+    Tuple       by its items, which must be hashable too
+An Integer and a Real are never the same key, even if they're equal numbers. Nothing else can be a
+key: a List, for one, can change after it's hashed, which would lose its entry. A Tuple is copied
+when it's made into a key, so setting one of its items later doesn't change the key, and the key
+that comes back out of the map is a new Tuple.
+
+The entries are kept in the order they were inserted, which is the order keys and iteration see
+them in. Removing an entry leaves a hole where it was, and inserting after a lot of removals
+compacts the entries to get rid of the holes, which moves the ones after a hole back. Every entry
+is numbered in the order it was inserted, and a `for` loop remembers its place by that number
+instead of by index, so compacting never makes it skip or repeat an entry.
+*/
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use super::{Identity, Real, Str, Tuple, Value, ValueType};
+
+/// a hashable copy of a Value
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Integer(i64),
+    Real(u64),
+    Str(Str),
+    Tuple(Rc<[Key]>),
+}
+
+impl Key {
+    /// the key for `value`, or the type of the value in it that can't be
+    /// hashed
+    pub fn new(value: &Value) -> Result<Key, ValueType> {
+        Ok(match value {
+            Value::Integer(v) => Key::Integer(*v),
+            Value::Real(v) => Key::Real(v.to_bits()),
+            Value::Str(v) => Key::Str(v.clone()),
+            Value::Tuple(t) => {
+                Key::Tuple(t.iter().map(|v| Key::new(&v)).collect::<Result<_, _>>()?)
+            }
+            _ => return Err(value.get_type()),
+        })
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Key::Integer(v) => (*v).into(),
+            Key::Real(v) => Real::from_bits(*v).into(),
+            Key::Str(v) => v.clone().into(),
+            Key::Tuple(items) => Tuple::from_iter(items.iter().map(Key::to_value)).into(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Map {
+    inner: Rc<RefCell<MapInner>>,
+}
+
+struct Slot {
+    /// the number of entries inserted into the map before this one
+    order: u64,
+    /// none once the entry is removed
+    entry: Option<(Key, Value)>,
+}
+
+struct MapInner {
+    /// the entries in insertion order, including the holes of removed ones
+    slots: Vec<Slot>,
+    /// the index of each key's slot
+    indices: HashMap<Key, usize>,
+    /// the order of the next entry
+    next_order: u64,
+}
+
+impl MapInner {
+    /// removes the holes from `slots`, if there are more of them than
+    /// entries
+    fn compact(&mut self) {
+        let holes = self.slots.len() - self.indices.len();
+        if holes < 8 || holes <= self.indices.len() {
+            return;
+        }
+        self.slots.retain(|slot| slot.entry.is_some());
+        for (index, slot) in self.slots.iter().enumerate() {
+            let (key, _) = slot.entry.as_ref().unwrap();
+            *self.indices.get_mut(key).unwrap() = index;
+        }
+    }
+}
+
+impl Map {
+    /// a map of `entries`. when a key is there more than once, the last value
+    /// is the one that's kept
+    pub fn new(entries: Vec<(Key, Value)>) -> Map {
+        let map = Map {
+            inner: Rc::new(RefCell::new(MapInner {
+                slots: Vec::new(),
+                indices: HashMap::new(),
+                next_order: 0,
+            })),
+        };
+        for (key, value) in entries {
+            map.set(key, value);
+        }
+        map
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.borrow().indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &Key) -> Option<Value> {
+        let inner = self.inner.borrow();
+        let index = *inner.indices.get(key)?;
+        inner.slots[index]
+            .entry
+            .as_ref()
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn contains(&self, key: &Key) -> bool {
+        self.inner.borrow().indices.contains_key(key)
+    }
+
+    /// sets the value of `key`, and returns the one it replaced
+    pub fn set(&self, key: Key, mut value: Value) -> Option<Value> {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(&index) = inner.indices.get(&key) {
+            let (_, item) = inner.slots[index].entry.as_mut().unwrap();
+            mem::swap(item, &mut value);
+            return Some(value);
+        }
+        inner.compact();
+        let index = inner.slots.len();
+        let order = inner.next_order;
+        inner.next_order += 1;
+        inner.slots.push(Slot {
+            order,
+            entry: Some((key.clone(), value)),
+        });
+        inner.indices.insert(key, index);
+        None
+    }
+
+    /// removes the entry of `key`, and returns its value
+    pub fn remove(&self, key: &Key) -> Option<Value> {
+        let mut inner = self.inner.borrow_mut();
+        let index = inner.indices.remove(key)?;
+        let (_, value) = inner.slots[index].entry.take().unwrap();
+        Some(value)
+    }
+
+    /// the keys, in insertion order
+    pub fn keys(&self) -> Vec<Value> {
+        let inner = self.inner.borrow();
+        inner
+            .slots
+            .iter()
+            .filter_map(|slot| slot.entry.as_ref())
+            .map(|(key, _)| key.to_value())
+            .collect()
+    }
+
+    /// the entries as (key, value) Tuples, in insertion order
+    pub fn to_vec(&self) -> Vec<Value> {
+        let inner = self.inner.borrow();
+        inner
+            .slots
+            .iter()
+            .filter_map(|slot| slot.entry.as_ref())
+            .map(|(key, value)| {
+                Tuple::from_iter(vec![key.to_value(), value.clone()].into_iter()).into()
+            })
+            .collect()
+    }
+
+    /// the first entry inserted after the one numbered `order`, or the first
+    /// entry if `order` is none, with its number
+    pub fn next_after(&self, order: Option<u64>) -> Option<(u64, Value, Value)> {
+        let inner = self.inner.borrow();
+        let index = match order {
+            Some(order) => inner.slots.partition_point(|slot| slot.order <= order),
+            None => 0,
+        };
+        inner.slots[index..].iter().find_map(|slot| {
+            let (key, value) = slot.entry.as_ref()?;
+            Some((slot.order, key.to_value(), value.clone()))
+        })
+    }
+}
+
+impl Identity for Map {
+    fn identity(&self) -> usize {
+        Rc::as_ptr(&self.inner).cast::<()>() as usize
+    }
+}
@@ -1,6 +1,10 @@
 mod buffer;
+mod debug;
 mod function;
 mod list;
+mod map;
+mod record;
+mod str;
 mod table;
 mod tuple;
 mod value;
@@ -8,6 +12,9 @@ mod value;
 pub use buffer::Buffer;
 pub use function::Function;
 pub use list::List;
+pub use map::{Key, Map};
+pub use record::RecordType;
+pub use str::Str;
 pub use table::Table;
 pub use tuple::{Tuple, TupleWeak};
 pub use value::{Identity, Integer, NativeFn, Real, Unknown, Value, ValueTryIntoError, ValueType};
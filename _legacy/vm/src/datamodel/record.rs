@@ -0,0 +1,43 @@
+use std::rc::Rc;
+
+use super::{Identity, Str};
+
+/// the name of a record and the names of its fields. a record is a Tuple with
+/// its RecordType as item 0, and its fields after that, in order
+#[derive(Clone)]
+pub struct RecordType {
+    inner: Rc<RecordTypeInner>,
+}
+
+struct RecordTypeInner {
+    name: Str,
+    fields: Vec<Str>,
+}
+
+impl RecordType {
+    pub fn new(name: Str, fields: Vec<Str>) -> RecordType {
+        RecordType {
+            inner: Rc::new(RecordTypeInner { name, fields }),
+        }
+    }
+
+    pub fn name(&self) -> &Str {
+        &self.inner.name
+    }
+
+    pub fn fields(&self) -> &[Str] {
+        &self.inner.fields
+    }
+
+    /// the index in a record's Tuple of the field called `name`
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        let index = self.inner.fields.iter().position(|f| f.as_str() == name)?;
+        Some(index + 1)
+    }
+}
+
+impl Identity for RecordType {
+    fn identity(&self) -> usize {
+        Rc::as_ptr(&self.inner).cast::<()>() as usize
+    }
+}
@@ -0,0 +1,71 @@
+use std::rc::Rc;
+
+use super::Identity;
+
+/// immutable utf-8 text. clones share the text, and indexes into it are byte
+/// offsets, which must be on char boundaries
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Str {
+    text: Rc<str>,
+}
+
+impl Str {
+    pub fn new(text: &str) -> Str {
+        Str {
+            text: Rc::from(text),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// the length in bytes
+    pub fn len(&self) -> usize {
+        self.text.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn concat(&self, other: &Str) -> Str {
+        let mut text = String::with_capacity(self.len() + other.len());
+        text.push_str(&self.text);
+        text.push_str(&other.text);
+        Str::from(text)
+    }
+
+    pub fn is_char_boundary(&self, index: usize) -> bool {
+        self.text.is_char_boundary(index)
+    }
+
+    /// the text from byte `a` to byte `b`, if they're in order, in range and
+    /// on char boundaries
+    pub fn get_slice(&self, a: usize, b: usize) -> Option<Str> {
+        self.text.get(a..b).map(Str::new)
+    }
+
+    /// the char that starts at byte `index`, as a Str of its own
+    pub fn char_at(&self, index: usize) -> Option<Str> {
+        let c = self.text.get(index..)?.chars().next()?;
+        Some(Str::new(c.encode_utf8(&mut [0; 4])))
+    }
+
+    /// the byte offset of the first place where `needle` is
+    pub fn find(&self, needle: &Str) -> Option<usize> {
+        self.text.find(&*needle.text)
+    }
+}
+
+impl From<String> for Str {
+    fn from(t: String) -> Str {
+        Str { text: Rc::from(t) }
+    }
+}
+
+impl Identity for Str {
+    fn identity(&self) -> usize {
+        Rc::as_ptr(&self.text).cast::<u8>() as usize
+    }
+}
@@ -38,6 +38,18 @@ impl Table {
         Some(val.clone())
     }
 
+    /// the entry with the smallest key after `key`, or the first entry if
+    /// `key` is none
+    pub fn next_after(&self, key: Option<u64>) -> Option<(u64, Value)> {
+        let items = self.items.borrow();
+        let index = match key {
+            Some(key) => items.partition_point(|(k, _)| *k <= key),
+            None => 0,
+        };
+        let (key, val) = items.get(index)?;
+        Some((*key, val.clone()))
+    }
+
     pub fn set(&self, key: u64, mut value: Value) -> Option<Value> {
         let mut items = self.items.borrow_mut();
         match items.binary_search_by_key(&key, |(k, _)| *k) {
@@ -3,7 +3,7 @@ use std::cmp::Ordering;
 use std::convert::TryInto;
 use std::rc::Rc;
 
-use super::{Buffer, Function, List, Table, Tuple, TupleWeak};
+use super::{Buffer, Function, List, Map, RecordType, Str, Table, Tuple, TupleWeak};
 
 pub type Integer = i64;
 pub type Real = f64;
@@ -82,7 +82,8 @@ macro_rules! create_value_enum {
 }
 
 create_value_enum! {
-    Integer, Real, Tuple, TupleWeak, Table, List, Buffer, Function, NativeFn, Unknown
+    Integer, Real, Tuple, TupleWeak, Table, List, Buffer, Function, NativeFn, Unknown, Str, Map,
+    RecordType
 }
 
 pub struct ValueTryIntoError {
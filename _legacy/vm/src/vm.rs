@@ -1,6 +1,6 @@
 use std::mem;
 
-use crate::bytecode::{OpAction, OpError};
+use crate::bytecode::{OpAction, OpError, OpSpan};
 use crate::datamodel::{Function, Value};
 
 use super::CallFrame;
@@ -26,6 +26,20 @@ impl VirtualMachine {
         }
     }
 
+    /// the source span of the op that ran last in each frame, innermost
+    /// first. after an OpError, the first span is the op that failed, and
+    /// the rest are the calls that led to it. frames whose function has no
+    /// spans are None
+    pub fn backtrace(&self) -> Vec<Option<OpSpan>> {
+        let mut spans = Vec::new();
+        let mut frame = self.frame.as_deref();
+        while let Some(f) = frame {
+            spans.push(f.span());
+            frame = f.parent.as_deref();
+        }
+        spans
+    }
+
     pub fn step(&mut self) -> Result<OpAction, OpError> {
         let frame = self.frame.as_mut().unwrap();
         frame.exec()
@@ -0,0 +1,304 @@
+/*
+The surface AST is what the parser builds from a TokenStream. It stays close to the source: names
+are still names, and every node keeps the byte span it was parsed from, so later passes can point
+diagnostics at the code that caused them. Lowering it into the stage0 tree is a separate step.
+*/
+use peanut_script_compiler::stage0::{BinaryOpType, UnaryOpType};
+
+/// a range of bytes in the source, in the same form as a TokenItem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span {
+            start,
+            len: end - start,
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// the span from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+/// the statements of a whole source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+/// statements in braces; the span includes the braces
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    Fn(FnDef),
+    /// `let pattern = value`, or `let name` without a value
+    Let {
+        pattern: Pattern,
+        value: Option<Expr>,
+    },
+    /// `const name = value`, a module item that can't be assigned to
+    Const {
+        name: Ident,
+        value: Expr,
+    },
+    /// `var name` or `var name = value`, a module item that can
+    Var {
+        name: Ident,
+        value: Option<Expr>,
+    },
+    Import(Import),
+    Record(Record),
+    IfElse(IfElse),
+    Loop(Loop),
+    For(For),
+    Break {
+        label: Option<Ident>,
+    },
+    Continue {
+        label: Option<Ident>,
+    },
+    Return(Option<Expr>),
+    /// `place = value`, or a compound assignment like `place += value`,
+    /// where `op` is the binary op that's applied
+    Assign {
+        place: Expr,
+        op: Option<BinaryOpType>,
+        value: Expr,
+    },
+    /// `(a, b) = value` or `[a, ..rest] = value`, where the pattern's names
+    /// are places
+    AssignPattern {
+        pattern: Pattern,
+        value: Expr,
+    },
+    Expr(Expr),
+    /// a statement that couldn't be parsed; see parser::parse_with_recovery
+    Error,
+}
+
+/// `import foo.bar`, or `import foo.bar as baz`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// the dotted path, which has at least one name
+    pub path: Vec<Ident>,
+    pub alias: Option<Ident>,
+}
+
+impl Import {
+    /// the name the module is bound to: the alias, or the last name of the
+    /// path
+    pub fn name(&self) -> &Ident {
+        self.alias.as_ref().unwrap_or(self.path.last().unwrap())
+    }
+
+    /// the path, with the names joined by periods
+    pub fn dotted_path(&self) -> String {
+        let names: Vec<_> = self.path.iter().map(|i| i.name.as_str()).collect();
+        names.join(".")
+    }
+
+    /// the span of the path
+    pub fn path_span(&self) -> Span {
+        self.path[0].span.to(self.path.last().unwrap().span)
+    }
+}
+
+/// `record Name { a, b }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: Ident,
+    pub fields: Vec<Ident>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FnDef {
+    pub name: Ident,
+    pub params: Vec<Pattern>,
+    pub body: Block,
+}
+
+/// what a value is taken apart into, by a `let`, a parameter or an
+/// assignment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternKind {
+    /// `_`, which matches anything and keeps nothing
+    Wildcard,
+    /// a name to bind; in an assignment, any place, like `a[i]`
+    Place(Expr),
+    /// `(a, b)`, a seq with exactly these items
+    Tuple(Vec<Pattern>),
+    /// `[a, b]`, like a tuple pattern, or with a rest, like
+    /// `[a, ..rest, b]`, a seq with at least these items
+    List {
+        items: Vec<Pattern>,
+        rest: Option<Rest>,
+    },
+}
+
+/// `..rest` in a list pattern, which is a List of the items that the other
+/// patterns don't match, or just `..`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rest {
+    /// how many of the list's items come before it
+    pub index: usize,
+    pub place: Option<Expr>,
+    pub span: Span,
+}
+
+impl Pattern {
+    /// a pattern that's just `name`
+    pub fn name(name: Ident) -> Pattern {
+        let expr = Expr {
+            kind: ExprKind::Name(name.name),
+            span: name.span,
+        };
+        Pattern {
+            kind: PatternKind::Place(expr),
+            span: name.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfElse {
+    pub if_: If,
+    pub else_if: Vec<If>,
+    pub else_: Option<Block>,
+}
+
+/// a condition and its block; the span starts at the `if` keyword
+#[derive(Debug, Clone, PartialEq)]
+pub struct If {
+    pub condition: Expr,
+    pub body: Block,
+    pub span: Span,
+}
+
+/// `loop { }`, or `while condition { }`, with an optional `label:` in front
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub label: Option<Ident>,
+    pub condition: Option<Expr>,
+    pub body: Block,
+}
+
+/// `for pattern in seq { }`, with an optional `label:` in front
+#[derive(Debug, Clone, PartialEq)]
+pub struct For {
+    pub label: Option<Ident>,
+    pub pattern: Pattern,
+    pub seq: Expr,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    /// whether the expression can be assigned to
+    pub fn is_place(&self) -> bool {
+        matches!(
+            self.kind,
+            ExprKind::Name(_) | ExprKind::Index { .. } | ExprKind::Field { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Int(i64),
+    Real(f64),
+    Bool(bool),
+    None,
+    /// a string literal, already decoded
+    Str(Vec<u8>),
+    /// a byte string literal, which is always a Buffer
+    Bytes(Vec<u8>),
+    /// a string with embedded expressions, in source order
+    Interpolated(Vec<InterpolationPart>),
+    Name(String),
+    /// `(a, b)`, or `(a,)` with one item, or `()`
+    Tuple(Vec<Expr>),
+    /// `[a, b]`
+    List(Vec<Expr>),
+    Binary {
+        op: BinaryOpType,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOpType,
+        expr: Box<Expr>,
+    },
+    Call {
+        func: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    /// `fn(a, b) { ... }`, a function without a name
+    Fn {
+        params: Vec<Pattern>,
+        body: Block,
+    },
+    /// `seq[index]`
+    Index {
+        seq: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `expr.name`
+    Field {
+        expr: Box<Expr>,
+        name: Ident,
+    },
+    /// `seq[start..end]`, where either bound can be left out
+    Slice {
+        seq: Box<Expr>,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+    },
+    /// an expression that couldn't be parsed
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpolationPart {
+    /// decoded text, and the span of its token
+    Text(Vec<u8>, Span),
+    Expr(Expr),
+}
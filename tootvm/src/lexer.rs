@@ -0,0 +1,1832 @@
+/*
+The lexer turns source bytes into a TokenStream. It handles the low-level parsing tasks, like
+telling keywords apart from identifiers and decoding number literals, so that the parser only ever
+has to reason about whole tokens.
+
+Number literals are decoded into the same payloads as the vm's LiteralValue: integers are i64 and
+reals are f64. Decimal integers must fit in an i64; hex (0x) and binary (0b) literals may use all
+64 bits, and are reinterpreted as two's complement (so 0xFFFF_FFFF_FFFF_FFFF is -1). Underscores
+can be used as separators anywhere after the first digit.
+
+String literals are decoded into the text that ends up in a Str constant. Both quote styles
+support the same escapes:
+    \n \r \t     line feed, carriage return and tab
+    \0           null
+    \\ \" \'     backslash and quotes
+    \{ \}        braces
+    \xHH         a 7bit ascii byte, given as exactly 2 hex digits (up to \x7F)
+    \uHHHHHH     a 24bit unicode code point, given as exactly 6 hex digits, encoded as utf-8
+
+Double quoted strings can embed expressions in braces: `"total: {x + 1}"`. A string that does is
+lexed as a sequence of tokens instead of a single DoubleQuoteString:
+    InterpolatedStringStart     the opening quote
+    StringPart                  the decoded text between expressions, if there is any
+    InterpolationStart          the `{` before an expression
+    ...                         the expression's own tokens, lexed as usual
+    InterpolationEnd            the `}` after it
+    InterpolatedStringEnd       the closing quote
+Braces inside the expression are matched up, so only the `}` that closes the interpolation ends it,
+and the expression can hold strings of its own (interpolated or not). Use \{ and \} to put a
+literal brace in a string; single quoted, raw and byte strings never interpolate.
+
+Raw strings (`r"..."`) have no escapes at all; every byte up to the closing quote is kept as is. To
+put a `"` inside one, fence it with any number of hashes, and close it with a quote followed by the
+same number of hashes: `r#"say "hi""#`. Raw strings become plain Token::DoubleQuoteString tokens.
+
+Byte strings (`b"..."`, `b'...'` and the raw `br"..."`) become Token::ByteString, which always
+compiles to a Buffer, even where a string literal wouldn't. They use the same escapes, except that
+\xHH can be any byte (up to \xFF), and \u isn't allowed since there's no text to encode.
+
+Operators made of several characters are lexed as a single token, always taking the longest match
+(so `a<<=b` is `a <<= b`, never `a < <= b`). Operator tokens map one-to-one onto the stage0
+BinaryOpType and UnaryOpType variants; see Token::binary_op and Token::unary_op.
+
+Spaces, tabs, vertical tabs and form feeds are skipped. Line breaks may be LF, CRLF or a lone CR,
+and can be mixed within a file; each one becomes a single Token::LineFeed. Line breaks inside a
+string literal (of any kind) are always decoded as a single line feed byte.
+
+Block comments nest, so `/* a /* b */ c */` is a single comment, and code that already contains a
+comment can be commented out.
+
+The source must be valid utf-8, including inside strings and comments. Identifiers follow the
+unicode XID_Start and XID_Continue rules (with `_` also allowed to start one), so letters from any
+script can be used, while things like emoji and non-breaking spaces are reported as errors. ascii
+bytes are checked directly, and only non-ascii bytes are decoded.
+
+parse_lossless keeps everything the parser doesn't care about as trivia attached to the tokens:
+runs of whitespace, comments and doc comments (both `///` lines and doc blocks). Trivia that
+follows a token on the same line is that token's trailing trivia; trivia at the start of a line
+(after a LineFeed token, or at the start of the source) is leading trivia of the next token. Trivia
+after the last line break of the source has no token to go to, and is kept in
+TokenStream::end_trivia. Together, the tokens and their trivia cover every byte of the source, in
+order.
+
+relex updates a stream after an edit, without lexing the whole source again. Token boundaries are
+always places where the lexer starts fresh, so it starts at the token just before the edit, and
+lexes forward until it produces a token that is identical to an old token at the same (shifted)
+position. From there on, the old tokens are reused, with their offsets shifted by the edit.
+*/
+use std::ops::Range;
+
+use peanut_script_compiler::stage0::{BinaryOpType, UnaryOpType};
+
+#[derive(Debug)]
+pub struct TokenStream {
+    pub tokens: Vec<TokenItem>,
+    /// the offset of the last byte of each line break; a line break is a line
+    /// feed, a carriage return, or a CRLF pair (which counts as one break)
+    pub line_offsets: Vec<usize>,
+    /// trivia at the end of the source that doesn't trail a token; only used
+    /// by parse_lossless
+    pub end_trivia: Vec<TriviaItem>,
+}
+
+impl TokenStream {
+    pub fn new() -> Self {
+        TokenStream {
+            tokens: vec![],
+            line_offsets: vec![],
+            end_trivia: vec![],
+        }
+    }
+
+    /// concatenates every token and trivia item in the stream. for a stream
+    /// from parse_lossless, this reproduces `source` exactly
+    pub fn to_source(&self, source: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(source.len());
+        let mut write = |start: usize, len: usize| {
+            out.extend_from_slice(&source[start..start + len]);
+        };
+        for t in &self.tokens {
+            for trivia in &t.leading {
+                write(trivia.start, trivia.len);
+            }
+            write(t.start, t.len);
+            for trivia in &t.trailing {
+                write(trivia.start, trivia.len);
+            }
+        }
+        for trivia in &self.end_trivia {
+            write(trivia.start, trivia.len);
+        }
+        out
+    }
+
+    /// maps an offset to a line and a byte column, both counted from 1. see
+    /// LineIndex for char and UTF-16 columns, and for mapping back to offsets
+    pub fn offset_to_line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_offsets.partition_point(|i| *i < offset);
+        let line_start = match line {
+            0 => 0,
+            _ => self.line_offsets[line - 1] + 1,
+        };
+        (line + 1, offset - line_start + 1)
+    }
+}
+
+impl Default for TokenStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenItem {
+    pub token: Token,
+    pub start: usize,
+    pub len: usize,
+    /// trivia before the token; always empty unless lexed with parse_lossless
+    pub leading: Vec<TriviaItem>,
+    /// trivia after the token; always empty unless lexed with parse_lossless
+    pub trailing: Vec<TriviaItem>,
+}
+
+#[derive(Debug)]
+pub struct TriviaItem {
+    pub kind: TriviaKind,
+    pub start: usize,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+    DocComment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    LineFeed,
+    Exclaimation,
+    Hash,
+    Dollar,
+    Percent,
+    Ampersand,
+
+    LeftParen,
+    RightParen,
+    Asterisk,
+    Plus,
+    Comma,
+    Minus,
+    Period,
+    Slash,
+
+    Colon,
+    Semicolon,
+    LessThan,
+    Equal,
+    GreaterThan,
+    Question,
+    AtSign,
+
+    LeftSquare,
+    BackSlash,
+    RightSquare,
+    Caret,
+    Underscore,
+    Tick,
+
+    LeftCurly,
+    VerticalBar,
+    RightCurly,
+    Tilde,
+
+    DoubleEqual,
+    TripleEqual,
+    ExclaimationEqual,
+    LessThanEqual,
+    GreaterThanEqual,
+    DoubleLessThan,
+    DoubleGreaterThan,
+    DoubleAmpersand,
+    DoubleVerticalBar,
+    Arrow,
+    DoubleColon,
+    DoublePeriod,
+
+    PlusEqual,
+    MinusEqual,
+    AsteriskEqual,
+    SlashEqual,
+    PercentEqual,
+    DoubleLessThanEqual,
+    DoubleGreaterThanEqual,
+    AmpersandEqual,
+    VerticalBarEqual,
+    CaretEqual,
+
+    Keyword(Keyword),
+    Identifier,
+    IntLiteral(i64),
+    RealLiteral(f64),
+    SingleQuoteString(Vec<u8>),
+    DoubleQuoteString(Vec<u8>),
+    ByteString(Vec<u8>),
+    InterpolatedStringStart,
+    StringPart(Vec<u8>),
+    InterpolationStart,
+    InterpolationEnd,
+    InterpolatedStringEnd,
+    LineComment,
+    BlockComment,
+
+    Unknown,
+}
+
+impl Token {
+    /// the binary op for an infix operator token
+    pub fn binary_op(&self) -> Option<BinaryOpType> {
+        Some(match self {
+            Token::Plus => BinaryOpType::Add,
+            Token::Minus => BinaryOpType::Sub,
+            Token::Asterisk => BinaryOpType::Mul,
+            Token::Slash => BinaryOpType::Div,
+            Token::Percent => BinaryOpType::Rem,
+            Token::DoubleLessThan => BinaryOpType::Shl,
+            Token::DoubleGreaterThan => BinaryOpType::Shr,
+            Token::Ampersand => BinaryOpType::And,
+            Token::VerticalBar => BinaryOpType::Or,
+            Token::Caret => BinaryOpType::Xor,
+            Token::DoubleEqual => BinaryOpType::Equal,
+            Token::ExclaimationEqual => BinaryOpType::NotEqual,
+            Token::GreaterThan => BinaryOpType::Greater,
+            Token::GreaterThanEqual => BinaryOpType::GreaterOrEqual,
+            Token::LessThan => BinaryOpType::Less,
+            Token::LessThanEqual => BinaryOpType::LessOrEqual,
+            Token::TripleEqual => BinaryOpType::Identity,
+            Token::DoubleAmpersand => BinaryOpType::LogicAnd,
+            Token::DoubleVerticalBar => BinaryOpType::LogicOr,
+            _ => return None,
+        })
+    }
+
+    /// the unary op for a prefix operator token
+    pub fn unary_op(&self) -> Option<UnaryOpType> {
+        Some(match self {
+            Token::Minus => UnaryOpType::Neg,
+            Token::Tilde => UnaryOpType::Not,
+            Token::Exclaimation => UnaryOpType::LogicNot,
+            _ => return None,
+        })
+    }
+
+    /// the binary op applied by a compound assignment token, like `+=`
+    pub fn compound_assign_op(&self) -> Option<BinaryOpType> {
+        Some(match self {
+            Token::PlusEqual => BinaryOpType::Add,
+            Token::MinusEqual => BinaryOpType::Sub,
+            Token::AsteriskEqual => BinaryOpType::Mul,
+            Token::SlashEqual => BinaryOpType::Div,
+            Token::PercentEqual => BinaryOpType::Rem,
+            Token::DoubleLessThanEqual => BinaryOpType::Shl,
+            Token::DoubleGreaterThanEqual => BinaryOpType::Shr,
+            Token::AmpersandEqual => BinaryOpType::And,
+            Token::VerticalBarEqual => BinaryOpType::Or,
+            Token::CaretEqual => BinaryOpType::Xor,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyword {
+    Fn,
+    Let,
+    Const,
+    Var,
+    Import,
+    As,
+    Record,
+    If,
+    Else,
+    Loop,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    Return,
+    True,
+    False,
+    None,
+}
+
+impl Keyword {
+    pub fn from_bytes(b: &[u8]) -> Option<Keyword> {
+        Some(match b {
+            b"fn" => Keyword::Fn,
+            b"let" => Keyword::Let,
+            b"const" => Keyword::Const,
+            b"var" => Keyword::Var,
+            b"import" => Keyword::Import,
+            b"as" => Keyword::As,
+            b"record" => Keyword::Record,
+            b"if" => Keyword::If,
+            b"else" => Keyword::Else,
+            b"loop" => Keyword::Loop,
+            b"while" => Keyword::While,
+            b"for" => Keyword::For,
+            b"in" => Keyword::In,
+            b"break" => Keyword::Break,
+            b"continue" => Keyword::Continue,
+            b"return" => Keyword::Return,
+            b"true" => Keyword::True,
+            b"false" => Keyword::False,
+            b"none" => Keyword::None,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Keyword::Fn => "fn",
+            Keyword::Let => "let",
+            Keyword::Const => "const",
+            Keyword::Var => "var",
+            Keyword::Import => "import",
+            Keyword::As => "as",
+            Keyword::Record => "record",
+            Keyword::If => "if",
+            Keyword::Else => "else",
+            Keyword::Loop => "loop",
+            Keyword::While => "while",
+            Keyword::For => "for",
+            Keyword::In => "in",
+            Keyword::Break => "break",
+            Keyword::Continue => "continue",
+            Keyword::Return => "return",
+            Keyword::True => "true",
+            Keyword::False => "false",
+            Keyword::None => "none",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenError {
+    pub partial_stream: TokenStream,
+    pub error_kind: TokenErrorKind,
+    pub loc: usize,
+    pub len: usize,
+}
+
+#[derive(Debug)]
+pub enum TokenErrorKind {
+    UnknownToken(u8),
+    /// a non-ascii char that can't start a token, like an emoji
+    UnknownChar(char),
+    /// bytes that aren't valid utf-8; the error spans the bad sequence
+    InvalidUtf8,
+    UnclosedString,
+    UnclosedComment,
+    /// a number literal that doesn't fit in an i64 (or u64 for hex and
+    /// binary), or a real literal that is too large to be finite
+    NumberOverflow,
+    /// a number literal with missing digits (`0x`), or digits that are
+    /// invalid for its radix (`0b12`, `123abc`)
+    MalformedNumber,
+    /// an unknown escape, like `\q`, or a hex escape with too few digits
+    InvalidEscape,
+    /// a hex escape for a value that isn't allowed; `\x` only allows 7bit
+    /// ascii, and `\u` only allows unicode scalar values
+    InvalidCodePoint(u32),
+}
+
+/// replaces the bytes in `range` with `replacement`
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: Vec<u8>,
+}
+
+impl TextEdit {
+    pub fn apply(&self, source: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(source.len() + self.replacement.len());
+        out.extend_from_slice(&source[..self.range.start]);
+        out.extend_from_slice(&self.replacement);
+        out.extend_from_slice(&source[self.range.end..]);
+        out
+    }
+
+    /// maps an offset at or after the end of the edited range to where it is
+    /// after the edit
+    pub fn shift(&self, offset: usize) -> usize {
+        offset - self.range.end + self.range.start + self.replacement.len()
+    }
+}
+
+/// the part of a stream that relex lexed again
+#[derive(Debug)]
+pub struct Relexed {
+    /// the replaced part of the old source; old tokens and errors in this
+    /// range are gone, and those after it have moved by TextEdit::shift
+    pub old_range: Range<usize>,
+    /// the same part of the stream, in the new source
+    pub new_range: Range<usize>,
+    /// the errors found in new_range, in the same form as parse_with_recovery
+    pub errors: Vec<TokenErrorItem>,
+}
+
+/// a located error, as reported by parse_with_recovery
+#[derive(Debug)]
+pub struct TokenErrorItem {
+    pub error_kind: TokenErrorKind,
+    pub loc: usize,
+    pub len: usize,
+}
+
+static NULL: u8 = 0x00;
+static TAB: u8 = 0x09;
+static LINEFEED: u8 = 0x0A;
+static VERTICALTAB: u8 = 0x0B;
+static FORMFEED: u8 = 0x0C;
+static CARRIAGERETURN: u8 = 0x0D;
+static SPACE: u8 = 0x20;
+static EXCLAIMATION: u8 = 0x21;
+static DOUBLEQUOTE: u8 = 0x22;
+static HASH: u8 = 0x23;
+static DOLLAR: u8 = 0x24;
+static PERCENT: u8 = 0x25;
+static AMPERSAND: u8 = 0x26;
+static SINGLEQUOTE: u8 = 0x27;
+
+static LEFTPAREN: u8 = 0x28;
+static RIGHTPAREN: u8 = 0x29;
+static ASTERISK: u8 = 0x2A;
+static PLUS: u8 = 0x2B;
+static COMMA: u8 = 0x2C;
+static MINUS: u8 = 0x2D;
+static PERIOD: u8 = 0x2E;
+static SLASH: u8 = 0x2F;
+
+static COLON: u8 = 0x3A;
+static SEMICOLON: u8 = 0x3B;
+static LESSTHAN: u8 = 0x3C;
+static EQUAL: u8 = 0x3D;
+static GREATERTHAN: u8 = 0x3E;
+static QUESTION: u8 = 0x3F;
+static ATSIGN: u8 = 0x40;
+
+static LEFTSQUARE: u8 = 0x5B;
+static BACKSLASH: u8 = 0x5C;
+static RIGHTSQUARE: u8 = 0x5D;
+static CARET: u8 = 0x5E;
+static UNDERSCORE: u8 = 0x5F;
+static TICK: u8 = 0x60;
+
+static LEFTCURLY: u8 = 0x7B;
+static VERTICALBAR: u8 = 0x7C;
+static RIGHTCURLY: u8 = 0x7D;
+static TILDE: u8 = 0x7E;
+
+static UNICODE_START: u8 = 0x80;
+
+static DIGIT_START: u8 = 0x30;
+static DIGIT_END: u8 = 0x39;
+
+static UPPER_START: u8 = 0x41;
+static UPPER_END: u8 = 0x5A;
+
+static LOWER_START: u8 = 0x61;
+static LOWER_END: u8 = 0x7A;
+
+static LOWER_B: u8 = 0x62;
+static LOWER_E: u8 = 0x65;
+static LOWER_N: u8 = 0x6E;
+static LOWER_R: u8 = 0x72;
+static LOWER_T: u8 = 0x74;
+static LOWER_U: u8 = 0x75;
+static LOWER_X: u8 = 0x78;
+
+fn is_whitespace(c: u8) -> bool {
+    c == SPACE || c == TAB || c == VERTICALTAB || c == FORMFEED
+}
+
+fn is_line_break(c: u8) -> bool {
+    c == LINEFEED || c == CARRIAGERETURN
+}
+
+fn is_digit(c: u8) -> bool {
+    (DIGIT_START..=DIGIT_END).contains(&c)
+}
+
+fn is_identifier_start(c: u8) -> bool {
+    (UPPER_START..=UPPER_END).contains(&c)
+        || (LOWER_START..=LOWER_END).contains(&c)
+        || c == UNDERSCORE
+}
+
+fn is_identifier(c: u8) -> bool {
+    is_identifier_start(c) || is_digit(c)
+}
+
+fn is_unicode_identifier_start(ch: char) -> bool {
+    unicode_ident::is_xid_start(ch)
+}
+
+fn is_unicode_identifier(ch: char) -> bool {
+    unicode_ident::is_xid_continue(ch)
+}
+
+// ascii letters are folded to lower case, so `0X1F` and `1E5` are accepted too
+fn to_lower(c: u8) -> u8 {
+    c | 0x20
+}
+
+/// finds the longest operator at the start of `rest` that is more than one
+/// character long. single character operators are handled by Lexer::next_token
+fn multi_char_operator(rest: &[u8]) -> Option<(Token, usize)> {
+    // three character operators come first, so they win over their prefixes
+    let operators: [(&[u8], Token); 22] = [
+        (b"<<=", Token::DoubleLessThanEqual),
+        (b">>=", Token::DoubleGreaterThanEqual),
+        (b"===", Token::TripleEqual),
+        (b"==", Token::DoubleEqual),
+        (b"!=", Token::ExclaimationEqual),
+        (b"<=", Token::LessThanEqual),
+        (b">=", Token::GreaterThanEqual),
+        (b"<<", Token::DoubleLessThan),
+        (b">>", Token::DoubleGreaterThan),
+        (b"&&", Token::DoubleAmpersand),
+        (b"||", Token::DoubleVerticalBar),
+        (b"->", Token::Arrow),
+        (b"::", Token::DoubleColon),
+        (b"..", Token::DoublePeriod),
+        (b"+=", Token::PlusEqual),
+        (b"-=", Token::MinusEqual),
+        (b"*=", Token::AsteriskEqual),
+        (b"/=", Token::SlashEqual),
+        (b"%=", Token::PercentEqual),
+        (b"&=", Token::AmpersandEqual),
+        (b"|=", Token::VerticalBarEqual),
+        (b"^=", Token::CaretEqual),
+    ];
+    operators
+        .into_iter()
+        .find(|(op, _)| rest.starts_with(op))
+        .map(|(op, token)| (token, op.len()))
+}
+
+pub fn parse(source: &[u8]) -> Result<TokenStream, TokenError> {
+    let mut lexer = Lexer::new(source);
+    while lexer.cursor < source.len() {
+        let start = lexer.cursor;
+        match lexer.next_token() {
+            Ok(Some(token)) => lexer.push(token, start),
+            Ok(None) => {}
+            Err(e) => {
+                return Err(TokenError {
+                    partial_stream: lexer.ts,
+                    error_kind: e.error_kind,
+                    loc: e.loc,
+                    len: e.len,
+                });
+            }
+        }
+    }
+    if let Some(e) = lexer.unclosed_interpolation() {
+        return Err(TokenError {
+            partial_stream: lexer.ts,
+            error_kind: e.error_kind,
+            loc: e.loc,
+            len: e.len,
+        });
+    }
+    Ok(lexer.ts)
+}
+
+/// like parse, but keeps going after an error, so every problem in the source
+/// is reported at once. each bad token is replaced with a Token::Unknown that
+/// covers it, so the stream still spans the whole source.
+///
+/// an unclosed string or block comment would otherwise swallow the rest of the
+/// source, so lexing resumes at the end of the line where it was opened.
+pub fn parse_with_recovery(source: &[u8]) -> (TokenStream, Vec<TokenErrorItem>) {
+    let mut lexer = Lexer::new(source);
+    let mut errors = Vec::new();
+    while lexer.cursor < source.len() {
+        let start = lexer.cursor;
+        if let Some(token) = lexer.next_token_recovering(&mut errors) {
+            lexer.push(token, start);
+        }
+    }
+    errors.extend(lexer.unclosed_interpolation());
+    (lexer.ts, errors)
+}
+
+/// updates a stream from parse or parse_with_recovery after `edit`, where
+/// `source` is the new source, with the edit already applied. streams from
+/// parse_lossless aren't supported, since trivia isn't kept up to date.
+pub fn relex(ts: &mut TokenStream, source: &[u8], edit: &TextEdit) -> Relexed {
+    let mut old_tokens = std::mem::take(&mut ts.tokens);
+    let old_lines = std::mem::take(&mut ts.line_offsets);
+    // start from the token before the first one that touches the edit, since
+    // the edit could join onto it (like typing right after a name), and the
+    // lexer looks back one token when lexing numbers
+    let mut first = old_tokens
+        .partition_point(|t| t.start + t.len < edit.range.start)
+        .saturating_sub(1);
+    // an unclosed string or comment from parse_with_recovery could be closed
+    // by the edit, no matter how far away it is. the source before the edit
+    // hasn't changed, so it can be used to find them
+    let unclosed = old_tokens[..first].iter().position(|t| {
+        let c = source[t.start];
+        // raw and byte strings start with their prefix, and nothing else that
+        // starts with a letter can fail to lex
+        t.token == Token::Unknown
+            && (c == DOUBLEQUOTE || c == SINGLEQUOTE || c == SLASH || c == LOWER_R || c == LOWER_B)
+    });
+    if let Some(i) = unclosed {
+        first = i;
+    }
+    // the lexer doesn't know it's inside an interpolated string, so it has to
+    // start outside of them, at the opening quote of the outermost one
+    if let Some(i) = open_interpolations(&old_tokens[..first]).first() {
+        first = *i;
+    }
+    let relex_start = match old_tokens.get(first) {
+        Some(t) => t.start.min(edit.range.start),
+        None => 0,
+    };
+    let old_rest = old_tokens.split_off(first);
+    let mut lexer = Lexer::new(source);
+    lexer.cursor = relex_start;
+    lexer.ts.tokens = old_tokens;
+    lexer.ts.line_offsets = old_lines
+        .iter()
+        .copied()
+        .filter(|i| *i < relex_start)
+        .collect();
+
+    let edit_end = edit.range.start + edit.replacement.len();
+    let mut errors = Vec::new();
+    // the first old token that could still be reused, and how many
+    // interpolated strings are open after it
+    let mut j = 0;
+    let mut old_depth = 0;
+    let count_depth = |t: &TokenItem, depth: &mut usize| match t.token {
+        Token::InterpolatedStringStart => *depth += 1,
+        Token::InterpolatedStringEnd => *depth = depth.saturating_sub(1),
+        _ => {}
+    };
+    while lexer.cursor < source.len() {
+        let start = lexer.cursor;
+        let line_count = lexer.ts.line_offsets.len();
+        let error_count = errors.len();
+        let token = match lexer.next_token_recovering(&mut errors) {
+            Some(token) => token,
+            None => continue,
+        };
+        if start >= edit_end {
+            while j < old_rest.len()
+                && (old_rest[j].start < edit.range.end || edit.shift(old_rest[j].start) < start)
+            {
+                count_depth(&old_rest[j], &mut old_depth);
+                j += 1;
+            }
+            if let Some(old) = old_rest.get(j) {
+                // tokens can only be reused outside of interpolated strings,
+                // where the lexer has no state that the old tokens don't show
+                let mut depth = old_depth;
+                count_depth(old, &mut depth);
+                let same = depth == 0
+                    && lexer.strings.is_empty()
+                    && edit.shift(old.start) == start
+                    && old.len == lexer.cursor - start
+                    && old.token == token;
+                if same {
+                    let old_start = old.start;
+                    errors.truncate(error_count);
+                    lexer.ts.line_offsets.truncate(line_count);
+                    lexer.ts.line_offsets.extend(
+                        old_lines
+                            .iter()
+                            .filter(|i| **i >= old_start)
+                            .map(|i| edit.shift(*i)),
+                    );
+                    for mut t in old_rest.into_iter().skip(j) {
+                        t.start = edit.shift(t.start);
+                        lexer.ts.tokens.push(t);
+                    }
+                    *ts = lexer.ts;
+                    return Relexed {
+                        old_range: relex_start..old_start,
+                        new_range: relex_start..start,
+                        errors,
+                    };
+                }
+            }
+        }
+        lexer.push(token, start);
+    }
+    errors.extend(lexer.unclosed_interpolation());
+    *ts = lexer.ts;
+    Relexed {
+        old_range: relex_start..source.len() + edit.range.end - edit_end,
+        new_range: relex_start..source.len(),
+        errors,
+    }
+}
+
+/// like parse, but whitespace and comments are kept as trivia on the tokens
+/// instead of being dropped or emitted as tokens
+pub fn parse_lossless(source: &[u8]) -> Result<TokenStream, TokenError> {
+    let mut lexer = Lexer::new(source);
+    // trivia that hasn't been attached to a token yet
+    let mut pending = Vec::new();
+    while lexer.cursor < source.len() {
+        let start = lexer.cursor;
+        let kind = match lexer.next_token() {
+            Ok(Some(token)) => match token {
+                Token::LineComment | Token::BlockComment => {
+                    trivia_comment_kind(&source[start..lexer.cursor])
+                }
+                token => {
+                    let leading = lexer.attach_trailing(&mut pending);
+                    lexer.push(token, start);
+                    lexer.ts.tokens.last_mut().unwrap().leading = leading;
+                    continue;
+                }
+            },
+            Ok(None) => TriviaKind::Whitespace,
+            Err(e) => {
+                return Err(TokenError {
+                    partial_stream: lexer.ts,
+                    error_kind: e.error_kind,
+                    loc: e.loc,
+                    len: e.len,
+                });
+            }
+        };
+        pending.push(TriviaItem {
+            kind,
+            start,
+            len: lexer.cursor - start,
+        });
+    }
+    if let Some(e) = lexer.unclosed_interpolation() {
+        return Err(TokenError {
+            partial_stream: lexer.ts,
+            error_kind: e.error_kind,
+            loc: e.loc,
+            len: e.len,
+        });
+    }
+    let end_trivia = lexer.attach_trailing(&mut pending);
+    lexer.ts.end_trivia = end_trivia;
+    Ok(lexer.ts)
+}
+
+/// the indexes of the InterpolatedStringStart tokens that are still open at
+/// the end of `tokens`, outermost first
+fn open_interpolations(tokens: &[TokenItem]) -> Vec<usize> {
+    let mut open = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        match t.token {
+            Token::InterpolatedStringStart => open.push(i),
+            Token::InterpolatedStringEnd => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open
+}
+
+fn trivia_comment_kind(text: &[u8]) -> TriviaKind {
+    // `////` and `/***` are plain comments, like a row of slashes used as a
+    // separator, and so is the empty block comment `/**/`
+    let line_doc = text.starts_with(b"///") && !text.starts_with(b"////");
+    let block_doc = text.starts_with(b"/**") && !text.starts_with(b"/***") && text != b"/**/";
+    if line_doc || block_doc {
+        TriviaKind::DocComment
+    } else if text.starts_with(b"//") {
+        TriviaKind::LineComment
+    } else {
+        TriviaKind::BlockComment
+    }
+}
+
+struct Lexer<'a> {
+    source: &'a [u8],
+    cursor: usize,
+    ts: TokenStream,
+    /// the interpolated strings the cursor is inside of, innermost last
+    strings: Vec<OpenString>,
+}
+
+struct OpenString {
+    /// the offset of the opening quote
+    start: usize,
+    /// whether the cursor is inside an interpolation, rather than the text
+    in_expr: bool,
+    /// how many `{` inside the interpolation haven't been closed yet
+    braces: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a [u8]) -> Self {
+        Lexer {
+            source,
+            cursor: 0,
+            ts: TokenStream::new(),
+            strings: vec![],
+        }
+    }
+
+    /// once the whole source is lexed, reports an interpolated string that was
+    /// never closed. the error spans from its opening quote to the end
+    fn unclosed_interpolation(&mut self) -> Option<TokenErrorItem> {
+        let start = self.strings.first()?.start;
+        self.strings.clear();
+        Some(self.error(TokenErrorKind::UnclosedString, start))
+    }
+
+    /// pushes a token spanning from `start` up to the cursor
+    fn push(&mut self, token: Token, start: usize) {
+        self.ts.tokens.push(TokenItem {
+            token,
+            start,
+            len: self.cursor - start,
+            leading: vec![],
+            trailing: vec![],
+        });
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.source.get(self.cursor + offset).copied()
+    }
+
+    /// creates an error spanning from `loc` up to the cursor
+    fn error(&self, kind: TokenErrorKind, loc: usize) -> TokenErrorItem {
+        TokenErrorItem {
+            error_kind: kind,
+            loc,
+            len: self.cursor - loc,
+        }
+    }
+
+    /// if there's a line break at the cursor, records it in the stream's
+    /// line_offsets and moves the cursor past it
+    fn take_line_break(&mut self) -> bool {
+        let len = match self.peek(0) {
+            Some(c) if c == CARRIAGERETURN && self.peek(1) == Some(LINEFEED) => 2,
+            Some(c) if is_line_break(c) => 1,
+            _ => return false,
+        };
+        self.cursor += len;
+        self.ts.line_offsets.push(self.cursor - 1);
+        true
+    }
+
+    /// decodes the char at `at`, which must be the start of a non-ascii char.
+    /// if the bytes there aren't valid utf-8, returns the length of the bad
+    /// sequence instead
+    fn decode_char(&self, at: usize) -> Result<char, usize> {
+        let bytes = &self.source[at..self.source.len().min(at + 4)];
+        let valid = match std::str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(e) if e.valid_up_to() > 0 => {
+                std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap()
+            }
+            Err(e) => return Err(e.error_len().unwrap_or(bytes.len())),
+        };
+        Ok(valid.chars().next().unwrap())
+    }
+
+    /// moves the cursor past one char. if it's not valid utf-8, the bad bytes
+    /// are skipped, and reported in `error` unless it already holds an error
+    fn skip_char(&mut self, error: &mut Option<TokenErrorItem>) {
+        let at = self.cursor;
+        if self.source[at] < UNICODE_START {
+            self.cursor += 1;
+            return;
+        }
+        match self.decode_char(at) {
+            Ok(ch) => self.cursor += ch.len_utf8(),
+            Err(len) => {
+                self.cursor += len;
+                error.get_or_insert(self.error(TokenErrorKind::InvalidUtf8, at));
+            }
+        }
+    }
+
+    /// the length of the char at the cursor, if it can continue an identifier
+    fn identifier_char_len(&self) -> Option<usize> {
+        let c = self.peek(0)?;
+        if c < UNICODE_START {
+            return if is_identifier(c) { Some(1) } else { None };
+        }
+        match self.decode_char(self.cursor) {
+            Ok(ch) if is_unicode_identifier(ch) => Some(ch.len_utf8()),
+            _ => None,
+        }
+    }
+
+    /// moves `pending` trivia onto the end of the last token, if it's on the
+    /// same line. otherwise it's returned, to become leading trivia
+    fn attach_trailing(&mut self, pending: &mut Vec<TriviaItem>) -> Vec<TriviaItem> {
+        match self.ts.tokens.last_mut() {
+            Some(t) if t.token != Token::LineFeed => {
+                t.trailing.append(pending);
+                vec![]
+            }
+            _ => std::mem::take(pending),
+        }
+    }
+
+    fn skip_while(&mut self, f: impl Fn(u8) -> bool) {
+        while self.cursor < self.source.len() && f(self.source[self.cursor]) {
+            self.cursor += 1;
+        }
+    }
+
+    /// like next_token, but errors are pushed onto `errors`, and the bad token
+    /// is replaced with a Token::Unknown. see parse_with_recovery
+    fn next_token_recovering(&mut self, errors: &mut Vec<TokenErrorItem>) -> Option<Token> {
+        let start = self.cursor;
+        let line_count = self.ts.line_offsets.len();
+        match self.next_token() {
+            Ok(token) => token,
+            Err(mut e) => {
+                if let TokenErrorKind::UnclosedString | TokenErrorKind::UnclosedComment =
+                    e.error_kind
+                {
+                    self.ts.line_offsets.truncate(line_count);
+                    self.cursor = start;
+                    // only the text of an interpolated string can start with a
+                    // line break; keep it in the token, so it isn't empty
+                    self.take_line_break();
+                    self.skip_while(|c| !is_line_break(c));
+                    e.len = self.cursor - start;
+                }
+                errors.push(e);
+                Some(Token::Unknown)
+            }
+        }
+    }
+
+    /// lexes a single token starting at the cursor, and advances the cursor
+    /// past it. returns None for whitespace, which isn't kept in the stream.
+    fn next_token(&mut self) -> Result<Option<Token>, TokenErrorItem> {
+        if self.strings.last().is_some_and(|s| !s.in_expr) {
+            return self.lex_string_part().map(Some);
+        }
+        let start = self.cursor;
+        let c = self.source[start];
+        if c == LOWER_R || c == LOWER_B {
+            if let Some(result) = self.lex_prefixed_string() {
+                return result.map(Some);
+            }
+        }
+        if is_identifier_start(c) {
+            return Ok(Some(self.lex_identifier()));
+        }
+        if c >= UNICODE_START {
+            return match self.decode_char(start) {
+                Ok(ch) if is_unicode_identifier_start(ch) => Ok(Some(self.lex_identifier())),
+                Ok(ch) => {
+                    self.cursor += ch.len_utf8();
+                    Err(self.error(TokenErrorKind::UnknownChar(ch), start))
+                }
+                Err(len) => {
+                    self.cursor += len;
+                    Err(self.error(TokenErrorKind::InvalidUtf8, start))
+                }
+            };
+        }
+        if is_digit(c) {
+            return match self.lex_number() {
+                Ok(token) => Ok(Some(token)),
+                Err(kind) => Err(self.error(kind, start)),
+            };
+        }
+        if c == DOUBLEQUOTE || c == SINGLEQUOTE {
+            return self.lex_string(0, false).map(Some);
+        }
+        if c == SLASH {
+            if let Some(token) = self.lex_comment()? {
+                return Ok(Some(token));
+            }
+        }
+        if self.take_line_break() {
+            return Ok(Some(Token::LineFeed));
+        }
+        if let Some(s) = self.strings.last_mut() {
+            if c == LEFTCURLY {
+                s.braces += 1;
+            } else if c == RIGHTCURLY && s.braces == 0 {
+                s.in_expr = false;
+                self.cursor += 1;
+                return Ok(Some(Token::InterpolationEnd));
+            } else if c == RIGHTCURLY {
+                s.braces -= 1;
+            }
+        }
+        if let Some((token, len)) = multi_char_operator(&self.source[start..]) {
+            self.cursor += len;
+            return Ok(Some(token));
+        }
+        self.cursor += 1;
+        let token = match c {
+            x if is_whitespace(x) => {
+                self.skip_while(is_whitespace);
+                return Ok(None);
+            }
+            x if x == EXCLAIMATION => Token::Exclaimation,
+            x if x == HASH => Token::Hash,
+            x if x == DOLLAR => Token::Dollar,
+            x if x == PERCENT => Token::Percent,
+            x if x == AMPERSAND => Token::Ampersand,
+
+            x if x == LEFTPAREN => Token::LeftParen,
+            x if x == RIGHTPAREN => Token::RightParen,
+            x if x == ASTERISK => Token::Asterisk,
+            x if x == PLUS => Token::Plus,
+            x if x == COMMA => Token::Comma,
+            x if x == MINUS => Token::Minus,
+            x if x == PERIOD => Token::Period,
+            x if x == SLASH => Token::Slash,
+
+            x if x == COLON => Token::Colon,
+            x if x == SEMICOLON => Token::Semicolon,
+            x if x == LESSTHAN => Token::LessThan,
+            x if x == EQUAL => Token::Equal,
+            x if x == GREATERTHAN => Token::GreaterThan,
+            x if x == QUESTION => Token::Question,
+            x if x == ATSIGN => Token::AtSign,
+
+            x if x == LEFTSQUARE => Token::LeftSquare,
+            x if x == BACKSLASH => Token::BackSlash,
+            x if x == RIGHTSQUARE => Token::RightSquare,
+            x if x == CARET => Token::Caret,
+            x if x == TICK => Token::Tick,
+
+            x if x == LEFTCURLY => Token::LeftCurly,
+            x if x == VERTICALBAR => Token::VerticalBar,
+            x if x == RIGHTCURLY => Token::RightCurly,
+            x if x == TILDE => Token::Tilde,
+
+            x => return Err(self.error(TokenErrorKind::UnknownToken(x), start)),
+        };
+        Ok(Some(token))
+    }
+
+    fn lex_identifier(&mut self) -> Token {
+        let start = self.cursor;
+        while let Some(len) = self.identifier_char_len() {
+            self.cursor += len;
+        }
+        let text = &self.source[start..self.cursor];
+        // a lone underscore is a placeholder, not a name
+        if text == [UNDERSCORE] {
+            return Token::Underscore;
+        }
+        match Keyword::from_bytes(text) {
+            Some(k) => Token::Keyword(k),
+            None => Token::Identifier,
+        }
+    }
+
+    fn lex_number(&mut self) -> Result<Token, TokenErrorKind> {
+        let radix = match self.peek(1).map(to_lower) {
+            _ if self.peek(0) != Some(DIGIT_START) => 10,
+            Some(x) if x == LOWER_X => 16,
+            Some(b) if b == LOWER_B => 2,
+            _ => 10,
+        };
+        let result = if radix == 10 {
+            self.lex_decimal()
+        } else {
+            self.cursor += 2;
+            self.lex_radix(radix)
+        };
+        // a number running straight into a name, like `12px`, is malformed;
+        // the rest of the word is consumed so the error covers all of it
+        if self.identifier_char_len().is_some() {
+            while let Some(len) = self.identifier_char_len() {
+                self.cursor += len;
+            }
+            return Err(TokenErrorKind::MalformedNumber);
+        }
+        result
+    }
+
+    fn lex_radix(&mut self, radix: u32) -> Result<Token, TokenErrorKind> {
+        let mut value: u64 = 0;
+        let mut digits = 0;
+        let mut overflow = false;
+        while let Some(c) = self.peek(0) {
+            if c != UNDERSCORE {
+                let d = match (c as char).to_digit(radix) {
+                    Some(d) => d as u64,
+                    None => break,
+                };
+                match value
+                    .checked_mul(radix as u64)
+                    .and_then(|v| v.checked_add(d))
+                {
+                    Some(v) => value = v,
+                    None => overflow = true,
+                }
+                digits += 1;
+            }
+            self.cursor += 1;
+        }
+        if digits == 0 {
+            Err(TokenErrorKind::MalformedNumber)
+        } else if overflow {
+            Err(TokenErrorKind::NumberOverflow)
+        } else {
+            Ok(Token::IntLiteral(value as i64))
+        }
+    }
+
+    fn lex_decimal(&mut self) -> Result<Token, TokenErrorKind> {
+        let start = self.cursor;
+        let is_digit_or_sep = |c| is_digit(c) || c == UNDERSCORE;
+        self.skip_while(is_digit_or_sep);
+        let mut is_real = false;
+        // only treat the period as a decimal point if a digit follows it, so
+        // `1..2` and `1.foo` still lex as separate tokens. a number directly
+        // after a period is a field index, so `x.0.1` isn't read as `x . 0.1`
+        let after_period = match self.ts.tokens.last() {
+            Some(t) => t.token == Token::Period && t.start + t.len == start,
+            None => false,
+        };
+        if !after_period && self.peek(0) == Some(PERIOD) && self.peek(1).is_some_and(is_digit) {
+            is_real = true;
+            self.cursor += 1;
+            self.skip_while(is_digit_or_sep);
+        }
+        if self.peek(0).map(to_lower) == Some(LOWER_E) {
+            let sign = match self.peek(1) {
+                Some(c) if c == PLUS || c == MINUS => 1,
+                _ => 0,
+            };
+            if self.peek(1 + sign).is_some_and(is_digit) {
+                is_real = true;
+                self.cursor += 1 + sign;
+                self.skip_while(is_digit_or_sep);
+            }
+        }
+        let text: String = self.source[start..self.cursor]
+            .iter()
+            .filter(|c| **c != UNDERSCORE)
+            .map(|c| *c as char)
+            .collect();
+        if is_real {
+            match text.parse::<f64>() {
+                Ok(r) if r.is_finite() => Ok(Token::RealLiteral(r)),
+                Ok(_) => Err(TokenErrorKind::NumberOverflow),
+                Err(_) => Err(TokenErrorKind::MalformedNumber),
+            }
+        } else {
+            // the text only has digits, so the only way to fail is overflow
+            match text.parse::<i64>() {
+                Ok(i) => Ok(Token::IntLiteral(i)),
+                Err(_) => Err(TokenErrorKind::NumberOverflow),
+            }
+        }
+    }
+
+    /// returns None if the `r` or `b` at the cursor doesn't start a raw or
+    /// byte string, so it should be lexed as an identifier instead
+    fn lex_prefixed_string(&mut self) -> Option<Result<Token, TokenErrorItem>> {
+        let byte = self.peek(0) == Some(LOWER_B);
+        let raw = !byte || self.peek(1) == Some(LOWER_R);
+        let prefix = if byte && raw { 2 } else { 1 };
+        if raw {
+            let mut hashes = 0;
+            while self.peek(prefix + hashes) == Some(HASH) {
+                hashes += 1;
+            }
+            if self.peek(prefix + hashes) != Some(DOUBLEQUOTE) {
+                return None;
+            }
+            return Some(self.lex_raw_string(prefix, hashes, byte));
+        }
+        match self.peek(1) {
+            Some(q) if q == DOUBLEQUOTE || q == SINGLEQUOTE => Some(self.lex_string(1, true)),
+            _ => None,
+        }
+    }
+
+    /// lexes a string whose quote comes after `prefix` bytes (the `b` of a
+    /// byte string)
+    fn lex_string(&mut self, prefix: usize, byte: bool) -> Result<Token, TokenErrorItem> {
+        let start = self.cursor;
+        self.cursor += prefix;
+        let quote = self.source[self.cursor];
+        self.cursor += 1;
+        let interpolate = quote == DOUBLEQUOTE && !byte;
+        let line_count = self.ts.line_offsets.len();
+        let mut bytes = Vec::new();
+        // a bad escape doesn't end the string; keep going to find the closing
+        // quote, so the error is only reported once the whole token is known
+        let mut error = None;
+        loop {
+            let d = match self.peek(0) {
+                Some(d) => d,
+                None => {
+                    self.cursor = self.source.len();
+                    return Err(self.error(TokenErrorKind::UnclosedString, start));
+                }
+            };
+            if d == quote {
+                self.cursor += 1;
+                break;
+            }
+            if interpolate && d == LEFTCURLY {
+                // the text is lexed again by lex_string_part, one piece at a time
+                self.cursor = start + 1;
+                self.ts.line_offsets.truncate(line_count);
+                self.strings.push(OpenString {
+                    start,
+                    in_expr: false,
+                    braces: 0,
+                });
+                return Ok(Token::InterpolatedStringStart);
+            }
+            if d == BACKSLASH {
+                if let Err(e) = self.lex_escape(&mut bytes, byte) {
+                    error.get_or_insert(e);
+                }
+                continue;
+            }
+            if self.take_line_break() {
+                bytes.push(LINEFEED);
+                continue;
+            }
+            let at = self.cursor;
+            self.skip_char(&mut error);
+            bytes.extend_from_slice(&self.source[at..self.cursor]);
+        }
+        if let Some(e) = error {
+            return Err(e);
+        }
+        Ok(if byte {
+            Token::ByteString(bytes)
+        } else if quote == DOUBLEQUOTE {
+            Token::DoubleQuoteString(bytes)
+        } else {
+            Token::SingleQuoteString(bytes)
+        })
+    }
+
+    /// lexes the text of an interpolated string up to the next interpolation or
+    /// the closing quote, or the `{` or quote itself if it's at the cursor
+    fn lex_string_part(&mut self) -> Result<Token, TokenErrorItem> {
+        let start = self.cursor;
+        let mut bytes = Vec::new();
+        let mut error = None;
+        loop {
+            let d = match self.peek(0) {
+                Some(d) => d,
+                None => {
+                    // every open string ends here, so none of them can close
+                    self.strings.clear();
+                    return Err(self.error(TokenErrorKind::UnclosedString, start));
+                }
+            };
+            if d == DOUBLEQUOTE || d == LEFTCURLY {
+                if self.cursor > start {
+                    break;
+                }
+                self.cursor += 1;
+                if d == DOUBLEQUOTE {
+                    self.strings.pop();
+                    return Ok(Token::InterpolatedStringEnd);
+                }
+                self.strings.last_mut().unwrap().in_expr = true;
+                return Ok(Token::InterpolationStart);
+            }
+            if d == BACKSLASH {
+                if let Err(e) = self.lex_escape(&mut bytes, false) {
+                    error.get_or_insert(e);
+                }
+                continue;
+            }
+            if self.take_line_break() {
+                bytes.push(LINEFEED);
+                continue;
+            }
+            let at = self.cursor;
+            self.skip_char(&mut error);
+            bytes.extend_from_slice(&self.source[at..self.cursor]);
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(Token::StringPart(bytes)),
+        }
+    }
+
+    /// lexes a raw string, with `hashes` hashes between the prefix and the
+    /// opening quote. nothing inside is decoded, except for line breaks
+    fn lex_raw_string(
+        &mut self,
+        prefix: usize,
+        hashes: usize,
+        byte: bool,
+    ) -> Result<Token, TokenErrorItem> {
+        let start = self.cursor;
+        self.cursor += prefix + hashes + 1;
+        let mut bytes = Vec::new();
+        let mut error = None;
+        loop {
+            let d = match self.peek(0) {
+                Some(d) => d,
+                None => return Err(self.error(TokenErrorKind::UnclosedString, start)),
+            };
+            if d == DOUBLEQUOTE && (1..=hashes).all(|i| self.peek(i) == Some(HASH)) {
+                self.cursor += 1 + hashes;
+                break;
+            }
+            if self.take_line_break() {
+                bytes.push(LINEFEED);
+                continue;
+            }
+            let at = self.cursor;
+            self.skip_char(&mut error);
+            bytes.extend_from_slice(&self.source[at..self.cursor]);
+        }
+        if let Some(e) = error {
+            return Err(e);
+        }
+        Ok(if byte {
+            Token::ByteString(bytes)
+        } else {
+            Token::DoubleQuoteString(bytes)
+        })
+    }
+
+    /// decodes the escape at the cursor, and appends it to `out`. byte strings
+    /// allow any byte in \x, but have no \u
+    fn lex_escape(&mut self, out: &mut Vec<u8>, byte: bool) -> Result<(), TokenErrorItem> {
+        let start = self.cursor;
+        self.cursor += 1;
+        let c = match self.peek(0) {
+            Some(c) => c,
+            // let lex_string report the unclosed string
+            None => return Ok(()),
+        };
+        // line breaks are left for lex_string, so they are still counted
+        if !is_line_break(c) {
+            self.cursor += 1;
+        }
+        let b = match c {
+            x if x == LOWER_N => LINEFEED,
+            x if x == LOWER_R => CARRIAGERETURN,
+            x if x == LOWER_T => TAB,
+            x if x == DIGIT_START => NULL,
+            x if x == BACKSLASH || x == DOUBLEQUOTE || x == SINGLEQUOTE => x,
+            x if x == LEFTCURLY || x == RIGHTCURLY => x,
+            x if x == LOWER_X => {
+                let v = self.lex_escape_hex(2, start)?;
+                if v > 0x7F && !byte {
+                    return Err(self.error(TokenErrorKind::InvalidCodePoint(v), start));
+                }
+                v as u8
+            }
+            x if x == LOWER_U && !byte => {
+                let v = self.lex_escape_hex(6, start)?;
+                // surrogates and anything past 0x10FFFF can't be encoded
+                let ch = match char::from_u32(v) {
+                    Some(ch) => ch,
+                    None => return Err(self.error(TokenErrorKind::InvalidCodePoint(v), start)),
+                };
+                let mut buf = [0; 4];
+                out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                return Ok(());
+            }
+            _ => return Err(self.error(TokenErrorKind::InvalidEscape, start)),
+        };
+        out.push(b);
+        Ok(())
+    }
+
+    fn lex_escape_hex(&mut self, digits: usize, start: usize) -> Result<u32, TokenErrorItem> {
+        let mut value = 0;
+        for _ in 0..digits {
+            match self.peek(0).and_then(|c| (c as char).to_digit(16)) {
+                Some(d) => value = value * 16 + d,
+                None => return Err(self.error(TokenErrorKind::InvalidEscape, start)),
+            }
+            self.cursor += 1;
+        }
+        Ok(value)
+    }
+
+    /// returns None if the slash at the cursor doesn't start a comment
+    fn lex_comment(&mut self) -> Result<Option<Token>, TokenErrorItem> {
+        let start = self.cursor;
+        let mut error = None;
+        let token = match self.peek(1) {
+            Some(c) if c == SLASH => {
+                // line comment; the line break isn't part of the comment
+                while self.peek(0).is_some_and(|c| !is_line_break(c)) {
+                    self.skip_char(&mut error);
+                }
+                Token::LineComment
+            }
+            Some(c) if c == ASTERISK => {
+                // block comment; these nest, so count the levels still open
+                self.cursor += 2;
+                let mut depth = 1;
+                loop {
+                    if self.cursor + 1 >= self.source.len() {
+                        self.cursor = self.source.len();
+                        return Err(self.error(TokenErrorKind::UnclosedComment, start));
+                    }
+                    if self.peek(0) == Some(SLASH) && self.peek(1) == Some(ASTERISK) {
+                        self.cursor += 2;
+                        depth += 1;
+                        continue;
+                    }
+                    if self.peek(0) == Some(ASTERISK) && self.peek(1) == Some(SLASH) {
+                        self.cursor += 2;
+                        depth -= 1;
+                        if depth == 0 {
+                            break Token::BlockComment;
+                        }
+                        continue;
+                    }
+                    if !self.take_line_break() {
+                        self.skip_char(&mut error);
+                    }
+                }
+            }
+            _ => return Ok(None),
+        };
+        match error {
+            Some(e) => Err(e),
+            None => Ok(Some(token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let ts = parse(source.as_bytes()).expect("the source should lex");
+        ts.tokens.into_iter().map(|t| t.token).collect()
+    }
+
+    /// the error of a source that doesn't lex, with its location and length
+    fn error(source: &str) -> (TokenErrorKind, usize, usize) {
+        let e = parse(source.as_bytes()).expect_err("the source shouldn't lex");
+        (e.error_kind, e.loc, e.len)
+    }
+
+    /// the tokens of a stream, with where they are
+    fn located(ts: &TokenStream) -> Vec<(Token, usize, usize)> {
+        ts.tokens
+            .iter()
+            .map(|t| (t.token.clone(), t.start, t.len))
+            .collect()
+    }
+
+    #[test]
+    fn keywords_and_identifiers() {
+        assert_eq!(
+            tokens("let lets"),
+            [Token::Keyword(Keyword::Let), Token::Identifier]
+        );
+    }
+
+    #[test]
+    fn numbers_in_each_radix() {
+        assert_eq!(
+            tokens("10 0x1F 0B101 1_000 1.5 2e3"),
+            [
+                Token::IntLiteral(10),
+                Token::IntLiteral(31),
+                Token::IntLiteral(5),
+                Token::IntLiteral(1000),
+                Token::RealLiteral(1.5),
+                Token::RealLiteral(2000.0),
+            ]
+        );
+        // hex and binary literals can use the sign bit
+        assert_eq!(
+            tokens("0xFFFF_FFFF_FFFF_FFFF 9223372036854775807"),
+            [Token::IntLiteral(-1), Token::IntLiteral(i64::MAX)]
+        );
+    }
+
+    #[test]
+    fn number_overflow() {
+        let (kind, loc, len) = error("x = 9223372036854775808");
+        assert!(matches!(kind, TokenErrorKind::NumberOverflow));
+        assert_eq!((loc, len), (4, 19));
+        let (kind, _, _) = error("0x1_0000_0000_0000_0000");
+        assert!(matches!(kind, TokenErrorKind::NumberOverflow));
+        let (kind, _, _) = error("1e999");
+        assert!(matches!(kind, TokenErrorKind::NumberOverflow));
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        for source in ["0x", "0b12", "123abc"] {
+            let (kind, loc, len) = error(source);
+            assert!(
+                matches!(kind, TokenErrorKind::MalformedNumber),
+                "{}",
+                source
+            );
+            assert_eq!((loc, len), (0, source.len()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(
+            tokens(r#""a\n\t\\\"\x41\u0000e9\{" 'it\'s'"#),
+            [
+                Token::DoubleQuoteString(b"a\n\t\\\"A\xC3\xA9{".to_vec()),
+                Token::SingleQuoteString(b"it's".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_escapes() {
+        let (kind, loc, len) = error(r#"x = "ab\q""#);
+        assert!(matches!(kind, TokenErrorKind::InvalidEscape));
+        assert_eq!((loc, len), (7, 2));
+        let (kind, _, _) = error(r#""\x4""#);
+        assert!(matches!(kind, TokenErrorKind::InvalidEscape));
+        let (kind, _, _) = error(r#""\x80""#);
+        assert!(matches!(kind, TokenErrorKind::InvalidCodePoint(0x80)));
+        let (kind, _, _) = error(r#""\u00D800""#);
+        assert!(matches!(kind, TokenErrorKind::InvalidCodePoint(0xD800)));
+        let (kind, loc, _) = error("x = 'abc");
+        assert!(matches!(kind, TokenErrorKind::UnclosedString));
+        assert_eq!(loc, 4);
+    }
+
+    #[test]
+    fn operators_take_the_longest_match() {
+        assert_eq!(
+            tokens("a<<=b===c..d->e"),
+            [
+                Token::Identifier,
+                Token::DoubleLessThanEqual,
+                Token::Identifier,
+                Token::TripleEqual,
+                Token::Identifier,
+                Token::DoublePeriod,
+                Token::Identifier,
+                Token::Arrow,
+                Token::Identifier,
+            ]
+        );
+        assert_eq!(tokens("< <="), [Token::LessThan, Token::LessThanEqual]);
+    }
+
+    #[test]
+    fn operators_map_onto_stage0_ops() {
+        assert!(matches!(
+            Token::TripleEqual.binary_op(),
+            Some(BinaryOpType::Identity)
+        ));
+        assert!(Token::DoubleLessThanEqual.binary_op().is_none());
+        assert!(matches!(
+            Token::DoubleLessThanEqual.compound_assign_op(),
+            Some(BinaryOpType::Shl)
+        ));
+        assert!(matches!(Token::Tilde.unary_op(), Some(UnaryOpType::Not)));
+    }
+
+    #[test]
+    fn recovery_reports_every_error() {
+        let source = "a = 0x\nb = \"open\nc = 2 \x07 d";
+        let (ts, errors) = parse_with_recovery(source.as_bytes());
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|e| (format!("{:?}", e.error_kind), e.loc, e.len))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("MalformedNumber".to_string(), 4, 2),
+                ("UnclosedString".to_string(), 11, 5),
+                ("UnknownToken(7)".to_string(), 23, 1),
+            ]
+        );
+        // the bad tokens are replaced, and the lines after them still lex
+        let tokens: Vec<_> = ts.tokens.iter().map(|t| t.token.clone()).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Identifier,
+                Token::Equal,
+                Token::Unknown,
+                Token::LineFeed,
+                Token::Identifier,
+                Token::Equal,
+                Token::Unknown,
+                Token::LineFeed,
+                Token::Identifier,
+                Token::Equal,
+                Token::IntLiteral(2),
+                Token::Unknown,
+                Token::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn line_breaks_of_every_kind() {
+        let source = "a\r\nb\rc\n\t d\x0B\x0C";
+        let ts = parse(source.as_bytes()).expect("the source should lex");
+        let tokens: Vec<_> = ts.tokens.iter().map(|t| t.token.clone()).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Identifier,
+                Token::LineFeed,
+                Token::Identifier,
+                Token::LineFeed,
+                Token::Identifier,
+                Token::LineFeed,
+                Token::Identifier,
+            ]
+        );
+        // a CRLF is one line break, ending at its LF
+        assert_eq!(ts.line_offsets, [2, 4, 6]);
+        assert_eq!(ts.offset_to_line_col(3), (2, 1));
+        assert_eq!(ts.offset_to_line_col(9), (4, 3));
+    }
+
+    #[test]
+    fn line_breaks_in_strings_are_line_feeds() {
+        assert_eq!(
+            tokens("'a\r\nb\rc'"),
+            [Token::SingleQuoteString(b"a\nb\nc".to_vec())]
+        );
+    }
+
+    #[test]
+    fn identifiers_in_any_script() {
+        assert_eq!(
+            tokens("héllo 名前 _ß9"),
+            [Token::Identifier, Token::Identifier, Token::Identifier]
+        );
+        let (kind, loc, len) = error("a 😀");
+        assert!(matches!(kind, TokenErrorKind::UnknownChar('😀')));
+        assert_eq!((loc, len), (2, 4));
+        let (kind, loc, len) = error("a\u{a0}b");
+        assert!(matches!(kind, TokenErrorKind::UnknownChar('\u{a0}')));
+        assert_eq!((loc, len), (1, 2));
+    }
+
+    #[test]
+    fn invalid_utf8_anywhere() {
+        for (source, at) in [
+            (&b"a \xff b"[..], 2),
+            (b"\"ab\xc3\"", 3),
+            (b"// \xe2\x82\n", 3),
+        ] {
+            let e = parse(source).expect_err("the source shouldn't lex");
+            assert!(matches!(e.error_kind, TokenErrorKind::InvalidUtf8));
+            assert_eq!(e.loc, at);
+        }
+    }
+
+    #[test]
+    fn lossless_streams_give_back_their_source() {
+        let source = b"/// doc\nlet x = 1 // one\n  /* a b */ y\r\n//// rule\n  ";
+        let ts = parse_lossless(source).expect("the source should lex");
+        assert_eq!(ts.to_source(source), source);
+        let kinds = |trivia: &[TriviaItem]| trivia.iter().map(|t| t.kind).collect::<Vec<_>>();
+        let t = &ts.tokens;
+        assert_eq!(kinds(&t[0].leading), [TriviaKind::DocComment]);
+        assert_eq!(
+            kinds(&t[4].trailing),
+            [TriviaKind::Whitespace, TriviaKind::LineComment]
+        );
+        assert_eq!(
+            kinds(&t[6].leading),
+            [
+                TriviaKind::Whitespace,
+                TriviaKind::BlockComment,
+                TriviaKind::Whitespace
+            ]
+        );
+        // four slashes make a plain comment, not a doc comment
+        assert_eq!(kinds(&t[8].leading), [TriviaKind::LineComment]);
+        assert_eq!(kinds(&ts.end_trivia), [TriviaKind::Whitespace]);
+    }
+
+    #[test]
+    fn relex_resyncs_and_shifts_what_follows() {
+        let old = b"let a = 1\nlet b = 2\n";
+        let (mut ts, _) = parse_with_recovery(old);
+        let edit = TextEdit {
+            range: 4..5,
+            replacement: b"abc".to_vec(),
+        };
+        let new = edit.apply(old);
+        let relexed = relex(&mut ts, &new, &edit);
+        // lexing starts at the token before the edit, and stops at the first
+        // old token it lexes again
+        assert_eq!(relexed.old_range, 0..6);
+        assert_eq!(relexed.new_range, 0..8);
+        assert!(relexed.errors.is_empty());
+        let (fresh, _) = parse_with_recovery(&new);
+        assert_eq!(located(&ts), located(&fresh));
+        assert_eq!(ts.line_offsets, [11, 21]);
+    }
+
+    #[test]
+    fn relex_closes_a_string_before_the_edit() {
+        let old = b"a = \"x\nb = 1";
+        let (mut ts, errors) = parse_with_recovery(old);
+        assert_eq!(errors.len(), 1);
+        let edit = TextEdit {
+            range: 6..6,
+            replacement: b"\"".to_vec(),
+        };
+        let new = edit.apply(old);
+        let relexed = relex(&mut ts, &new, &edit);
+        assert!(relexed.errors.is_empty());
+        let (fresh, _) = parse_with_recovery(&new);
+        assert_eq!(located(&ts), located(&fresh));
+        assert_eq!(ts.line_offsets, fresh.line_offsets);
+    }
+
+    #[test]
+    fn block_comments_nest() {
+        assert_eq!(
+            tokens("a /* x /* y */ z */ b"),
+            [Token::Identifier, Token::BlockComment, Token::Identifier]
+        );
+        let (kind, loc, _) = error("a /* x /* y */ b");
+        assert!(matches!(kind, TokenErrorKind::UnclosedComment));
+        assert_eq!(loc, 2);
+    }
+
+    #[test]
+    fn raw_and_byte_strings() {
+        assert_eq!(
+            tokens(r###"r#"say "hi""# r"\n{x}""###),
+            [
+                Token::DoubleQuoteString(b"say \"hi\"".to_vec()),
+                Token::DoubleQuoteString(b"\\n{x}".to_vec()),
+            ]
+        );
+        assert_eq!(
+            tokens(r#"b"\xFF\n" b'a' br"\x""#),
+            [
+                Token::ByteString(b"\xFF\n".to_vec()),
+                Token::ByteString(b"a".to_vec()),
+                Token::ByteString(b"\\x".to_vec()),
+            ]
+        );
+        // there's no text in a byte string to encode a char into
+        let (kind, _, _) = error(r#"b"\u0000e9""#);
+        assert!(matches!(kind, TokenErrorKind::InvalidEscape));
+        // the closing quote needs as many hashes as the opening one
+        let (kind, _, _) = error(r##"r#"a""##);
+        assert!(matches!(kind, TokenErrorKind::UnclosedString));
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        assert_eq!(
+            tokens(r#""a{x + {1}["b{y}"]}\{c""#),
+            [
+                Token::InterpolatedStringStart,
+                Token::StringPart(b"a".to_vec()),
+                Token::InterpolationStart,
+                Token::Identifier,
+                Token::Plus,
+                Token::LeftCurly,
+                Token::IntLiteral(1),
+                Token::RightCurly,
+                Token::LeftSquare,
+                Token::InterpolatedStringStart,
+                Token::StringPart(b"b".to_vec()),
+                Token::InterpolationStart,
+                Token::Identifier,
+                Token::InterpolationEnd,
+                Token::InterpolatedStringEnd,
+                Token::RightSquare,
+                Token::InterpolationEnd,
+                Token::StringPart(b"{c".to_vec()),
+                Token::InterpolatedStringEnd,
+            ]
+        );
+        // single quoted strings never interpolate
+        assert_eq!(tokens("'{x}'"), [Token::SingleQuoteString(b"{x}".to_vec())]);
+        let (kind, loc, _) = error(r#"s = "a{x"#);
+        assert!(matches!(kind, TokenErrorKind::UnclosedString));
+        assert_eq!(loc, 4);
+    }
+}
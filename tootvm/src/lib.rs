@@ -0,0 +1,6 @@
+pub mod ast;
+pub mod lexer;
+pub mod line_index;
+pub mod loader;
+pub mod parser;
+pub mod resolve;
@@ -0,0 +1,209 @@
+/*
+LineIndex maps byte offsets in a source to line and column numbers, and back. It's built once per
+source, and every lookup is a binary search, so reporting lots of diagnostics stays cheap.
+
+Lines and columns are numbered from 1, like TokenStream::offset_to_line_col, and line breaks
+follow the same rules as the lexer: LF, CRLF or a lone CR. Columns can be counted in bytes, in
+chars, or in UTF-16 code units (which is what editor protocols like LSP expect). To count chars
+and UTF-16 units without keeping the source around, the index remembers where every non-ascii char
+is; invalid utf-8 bytes are counted as one char (and one UTF-16 unit) each.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnUnit {
+    Byte,
+    Char,
+    Utf16,
+}
+
+#[derive(Debug)]
+pub struct LineIndex {
+    /// the offset of the first byte of each line
+    line_starts: Vec<usize>,
+    /// every non-ascii char in the source, sorted by offset
+    wide_chars: Vec<WideChar>,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct WideChar {
+    offset: usize,
+    len_utf8: usize,
+    len_utf16: usize,
+}
+
+impl WideChar {
+    fn len(&self, unit: ColumnUnit) -> usize {
+        match unit {
+            ColumnUnit::Byte => self.len_utf8,
+            ColumnUnit::Char => 1,
+            ColumnUnit::Utf16 => self.len_utf16,
+        }
+    }
+}
+
+impl LineIndex {
+    pub fn new(source: &[u8]) -> LineIndex {
+        let mut line_starts = vec![0];
+        for (i, c) in source.iter().copied().enumerate() {
+            // the LF of a CRLF pair ends the line; the CR doesn't
+            let is_break = match c {
+                b'\n' => true,
+                b'\r' => source.get(i + 1) != Some(&b'\n'),
+                _ => false,
+            };
+            if is_break {
+                line_starts.push(i + 1);
+            }
+        }
+        let mut wide_chars = Vec::new();
+        let mut offset = 0;
+        for chunk in source.utf8_chunks() {
+            for (i, ch) in chunk.valid().char_indices() {
+                if !ch.is_ascii() {
+                    wide_chars.push(WideChar {
+                        offset: offset + i,
+                        len_utf8: ch.len_utf8(),
+                        len_utf16: ch.len_utf16(),
+                    });
+                }
+            }
+            offset += chunk.valid().len();
+            for _ in chunk.invalid() {
+                wide_chars.push(WideChar {
+                    offset,
+                    len_utf8: 1,
+                    len_utf16: 1,
+                });
+                offset += 1;
+            }
+        }
+        LineIndex {
+            line_starts,
+            wide_chars,
+            len: source.len(),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// the offset of the first byte on a line
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.line_starts.get(line.checked_sub(1)?).copied()
+    }
+
+    /// the last offset that is still on a line; this is the last byte of its
+    /// line break, or the end of the source for the last line
+    fn line_end(&self, line: usize) -> usize {
+        match self.line_starts.get(line) {
+            Some(next) => next - 1,
+            None => self.len,
+        }
+    }
+
+    /// the non-ascii chars that start within `start..end`
+    fn wide_chars_in(&self, start: usize, end: usize) -> &[WideChar] {
+        let a = self.wide_chars.partition_point(|w| w.offset < start);
+        let b = self.wide_chars.partition_point(|w| w.offset < end);
+        &self.wide_chars[a..b]
+    }
+
+    /// maps an offset to a line and column. an offset in the middle of a
+    /// multi-byte char is counted as if it were at the start of the char.
+    pub fn offset_to_line_col(&self, offset: usize, unit: ColumnUnit) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|s| *s <= offset);
+        let start = self.line_starts[line - 1];
+        let mut col = offset - start;
+        if unit != ColumnUnit::Byte {
+            for w in self.wide_chars_in(start, offset) {
+                let bytes = offset - w.offset;
+                if bytes >= w.len_utf8 {
+                    col = col - w.len_utf8 + w.len(unit);
+                } else {
+                    col -= bytes;
+                }
+            }
+        }
+        (line, col + 1)
+    }
+
+    /// maps a line and column back to an offset. returns None if the line
+    /// doesn't exist, if the column is past the end of the line, or if it
+    /// points into the middle of a char (like the second half of a UTF-16
+    /// surrogate pair).
+    pub fn line_col_to_offset(&self, line: usize, col: usize, unit: ColumnUnit) -> Option<usize> {
+        let start = self.line_start(line)?;
+        let end = self.line_end(line);
+        let target = col.checked_sub(1)?;
+        let mut units = 0;
+        let mut pos = start;
+        for w in self.wide_chars_in(start, end + 1) {
+            // the ascii chars before this one are a single unit each
+            let ascii = w.offset - pos;
+            if target < units + ascii {
+                return Some(pos + target - units);
+            }
+            units += ascii;
+            if target == units {
+                return Some(w.offset);
+            }
+            units += w.len(unit);
+            if target < units {
+                return None;
+            }
+            pos = w.offset + w.len_utf8;
+        }
+        let offset = pos + target - units;
+        if offset <= end {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 'é' is two bytes and one UTF-16 unit, '😀' is four bytes and two UTF-16 units
+    const SOURCE: &str = "a\r\néb😀c";
+
+    #[test]
+    fn columns_in_each_unit() {
+        let index = LineIndex::new(SOURCE.as_bytes());
+        assert_eq!(index.line_count(), 2);
+        assert_eq!(index.line_start(2), Some(3));
+        assert_eq!(index.line_start(0), None);
+        assert_eq!(index.offset_to_line_col(1, ColumnUnit::Char), (1, 2));
+        assert_eq!(index.offset_to_line_col(10, ColumnUnit::Byte), (2, 8));
+        assert_eq!(index.offset_to_line_col(10, ColumnUnit::Char), (2, 4));
+        assert_eq!(index.offset_to_line_col(10, ColumnUnit::Utf16), (2, 5));
+        // the middle of 'é' counts as its start
+        assert_eq!(index.offset_to_line_col(4, ColumnUnit::Char), (2, 1));
+    }
+
+    #[test]
+    fn line_cols_map_back_to_offsets() {
+        let index = LineIndex::new(SOURCE.as_bytes());
+        for unit in [ColumnUnit::Byte, ColumnUnit::Char, ColumnUnit::Utf16] {
+            for offset in [0, 1, 3, 5, 6, 10, 11] {
+                let (line, col) = index.offset_to_line_col(offset, unit);
+                assert_eq!(index.line_col_to_offset(line, col, unit), Some(offset));
+            }
+        }
+        // the second half of the surrogate pair
+        assert_eq!(index.line_col_to_offset(2, 4, ColumnUnit::Utf16), None);
+        assert_eq!(index.line_col_to_offset(2, 6, ColumnUnit::Char), None);
+        assert_eq!(index.line_col_to_offset(2, 0, ColumnUnit::Char), None);
+        assert_eq!(index.line_col_to_offset(3, 1, ColumnUnit::Char), None);
+    }
+
+    #[test]
+    fn invalid_utf8_is_one_unit_per_byte() {
+        let index = LineIndex::new(b"\xff\xfex");
+        assert_eq!(index.offset_to_line_col(2, ColumnUnit::Char), (1, 3));
+        assert_eq!(index.offset_to_line_col(2, ColumnUnit::Utf16), (1, 3));
+    }
+}
@@ -0,0 +1,300 @@
+/*
+The loader turns a source file, and every module it imports, into one stage0::Program.
+
+An import names a file on the loader's search path: `import foo.bar` is foo/bar.toot in the first
+directory of the search path that has it. The names of the path are pushed onto the directory one
+at a time, as path components, so the same import finds the same file on every platform, whatever
+its separator is.
+
+Each module is loaded once, however many modules import it, and they all refer to the same module
+in the program. A module is resolved after the modules it imports, since resolving it needs their
+exports, and it gets its index in the program when it's resolved. So a module always comes after
+the modules it imports, and the file that loading started with is the last module. Running the
+main function of each module in index order sets every `var` and `const` of a module before the
+modules that import it run.
+
+A module that imports itself, directly or through the modules it imports, can't come after itself,
+so an import cycle is an error, at the import that closes it. A module isn't resolved when a
+module it imports has errors; those are reported instead, at the module that has them.
+*/
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use peanut_script_compiler::stage0;
+
+use crate::ast::{self, Ident, Span};
+use crate::lexer::{self, TokenErrorItem};
+use crate::parser::{self, ParseError};
+use crate::resolve::{self, Exports, Imported, ResolveError};
+
+static EXTENSION: &str = "toot";
+
+#[derive(Debug)]
+pub struct LoadError {
+    /// the file that the error is in; the spans of the error are in its
+    /// source
+    pub path: PathBuf,
+    pub error_kind: LoadErrorKind,
+}
+
+#[derive(Debug)]
+pub enum LoadErrorKind {
+    /// the file couldn't be read
+    Io(io::ErrorKind),
+    Token(TokenErrorItem),
+    Parse(ParseError),
+    Resolve(ResolveError),
+    /// an import of a module that isn't on the search path; the span is the
+    /// module's path
+    NotFound {
+        span: Span,
+    },
+    /// an import that closes a cycle; the span is the module's path. `cycle`
+    /// has the modules in the cycle, starting with the imported one, and each
+    /// imports the next, with the last one doing it here
+    Cycle {
+        span: Span,
+        cycle: Vec<PathBuf>,
+    },
+}
+
+pub struct Loader {
+    search_path: Vec<PathBuf>,
+}
+
+impl Loader {
+    /// a loader that looks for modules in the directories of `search_path`,
+    /// in order
+    pub fn new(search_path: Vec<PathBuf>) -> Loader {
+        Loader { search_path }
+    }
+
+    /// the file that an import of `path` refers to, if there is one
+    pub fn find(&self, path: &[Ident]) -> Option<PathBuf> {
+        for dir in &self.search_path {
+            let mut file = dir.clone();
+            for name in path {
+                file.push(&name.name);
+            }
+            file.set_extension(EXTENSION);
+            if file.is_file() {
+                return file.canonicalize().ok();
+            }
+        }
+        None
+    }
+
+    /// loads the file at `path` and the modules it imports. every error in
+    /// every module is reported, not just the first
+    pub fn load(&self, path: &Path) -> Result<stage0::Program, Vec<LoadError>> {
+        let mut l = Load {
+            loader: self,
+            states: HashMap::new(),
+            stack: Vec::new(),
+            modules: Vec::new(),
+            exports: Vec::new(),
+            errors: Vec::new(),
+        };
+        match path.canonicalize() {
+            Ok(path) => {
+                l.load_module(path);
+            }
+            Err(e) => l.error(path, LoadErrorKind::Io(e.kind())),
+        }
+        if !l.errors.is_empty() {
+            return Err(l.errors);
+        }
+        Ok(stage0::Program { modules: l.modules })
+    }
+}
+
+struct Load<'a> {
+    loader: &'a Loader,
+    /// every module that loading reached, by its canonical path, with its
+    /// index once it's resolved. a module that's still loading, or that has
+    /// errors, has none
+    states: HashMap<PathBuf, Option<u32>>,
+    /// the modules that are loading, each imported by the one before it
+    stack: Vec<PathBuf>,
+    modules: Vec<stage0::Module>,
+    /// the exports of each module in `modules`
+    exports: Vec<Exports>,
+    errors: Vec<LoadError>,
+}
+
+impl<'a> Load<'a> {
+    fn error(&mut self, path: &Path, error_kind: LoadErrorKind) {
+        let path = path.to_path_buf();
+        self.errors.push(LoadError { path, error_kind });
+    }
+
+    /// loads the module at `path`, which must be canonical and not reached
+    /// before, and the modules it imports. returns its index, or none if it
+    /// or a module it imports has errors
+    fn load_module(&mut self, path: PathBuf) -> Option<u32> {
+        self.states.insert(path.clone(), None);
+        let source = match fs::read(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                self.error(&path, LoadErrorKind::Io(e.kind()));
+                return None;
+            }
+        };
+        let (ts, token_errors) = lexer::parse_with_recovery(&source);
+        let (module, parse_errors) = parser::parse_with_recovery(&source, &ts);
+        if !token_errors.is_empty() || !parse_errors.is_empty() {
+            for e in token_errors {
+                self.error(&path, LoadErrorKind::Token(e));
+            }
+            for e in parse_errors {
+                self.error(&path, LoadErrorKind::Parse(e));
+            }
+            return None;
+        }
+        self.stack.push(path);
+        let imports = self.load_imports(&module);
+        let path = self.stack.pop().unwrap();
+        let imports = imports?;
+        match resolve::resolve(&module, &imports) {
+            Ok((module, exports)) => {
+                let index = self.modules.len() as u32;
+                self.modules.push(module);
+                self.exports.push(exports);
+                self.states.insert(path, Some(index));
+                Some(index)
+            }
+            Err(errors) => {
+                for e in errors {
+                    self.error(&path, LoadErrorKind::Resolve(e));
+                }
+                None
+            }
+        }
+    }
+
+    /// loads the modules that the top-level imports of `module`, the one on
+    /// top of the stack, refer to. returns them the way resolve takes them,
+    /// or none if any of them has errors
+    fn load_imports(&mut self, module: &ast::Module) -> Option<HashMap<String, Imported>> {
+        let mut imports = HashMap::new();
+        let mut ok = true;
+        for statement in &module.body {
+            let import = match &statement.kind {
+                ast::StatementKind::Import(import) => import,
+                _ => continue,
+            };
+            let span = import.path_span();
+            let path = match self.loader.find(&import.path) {
+                Some(path) => path,
+                None => {
+                    let importer = self.stack.last().unwrap().clone();
+                    self.error(&importer, LoadErrorKind::NotFound { span });
+                    ok = false;
+                    continue;
+                }
+            };
+            if let Some(start) = self.stack.iter().position(|p| *p == path) {
+                let cycle = self.stack[start..].to_vec();
+                let importer = self.stack.last().unwrap().clone();
+                self.error(&importer, LoadErrorKind::Cycle { span, cycle });
+                ok = false;
+                continue;
+            }
+            let index = match self.states.get(&path) {
+                Some(&index) => index,
+                None => self.load_module(path),
+            };
+            match index {
+                Some(index) => {
+                    let imported = Imported {
+                        module: index,
+                        exports: self.exports[index as usize].clone(),
+                    };
+                    imports.insert(import.dotted_path(), imported);
+                }
+                None => ok = false,
+            }
+        }
+        if ok {
+            Some(imports)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// writes `files` into a fresh directory, and returns the directory
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tootvm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let file = dir.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn modules_load_once() {
+        let dir = write_files(
+            "imports",
+            &[
+                (
+                    "main.toot",
+                    "import lib.util\nimport twice\nreturn twice.f(util.x)",
+                ),
+                ("lib/util.toot", "const x = 1"),
+                (
+                    "twice.toot",
+                    "import lib.util as u\nfn f(a) { return a + u.x }",
+                ),
+            ],
+        );
+        let program = Loader::new(vec![dir.clone()])
+            .load(&dir.join("main.toot"))
+            .expect("the program should load");
+        // util is imported twice, but loaded once
+        assert_eq!(program.modules.len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_modules_and_cycles() {
+        let dir = write_files(
+            "errors",
+            &[
+                ("main.toot", "import a\nimport nowhere"),
+                ("a.toot", "import b"),
+                ("b.toot", "import a"),
+            ],
+        );
+        let errors = match Loader::new(vec![dir.clone()]).load(&dir.join("main.toot")) {
+            Ok(_) => panic!("the program shouldn't load"),
+            Err(errors) => errors,
+        };
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(errors.len(), 2);
+        // the cycle is reported at the import that closes it
+        assert_eq!(errors[0].path, dir.join("b.toot"));
+        match &errors[0].error_kind {
+            LoadErrorKind::Cycle { span, cycle } => {
+                assert_eq!(*span, Span::new(7, 8));
+                assert_eq!(*cycle, [dir.join("a.toot"), dir.join("b.toot")]);
+            }
+            kind => panic!("{:?}", kind),
+        }
+        assert_eq!(errors[1].path, dir.join("main.toot"));
+        assert!(matches!(
+            errors[1].error_kind,
+            LoadErrorKind::NotFound { span } if span == Span::new(16, 23)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,466 @@
+/*
+Expressions are parsed with a Pratt parser: each binary operator has a precedence, and an operand
+only takes in operators that bind tighter than the one it belongs to. From loosest to tightest:
+
+    ||                      LogicOr                     left
+    &&                      LogicAnd                    left
+    == != < <= > >= ===     Equal NotEqual Greater      none
+                            GreaterOrEqual Less
+                            LessOrEqual Identity
+    |                       Or                          left
+    ^                       Xor                         left
+    &                       And                         left
+    << >>                   Shl Shr                     left
+    + -                     Add Sub                     left
+    * / %                   Mul Div Rem                 left
+    - ~ !       (prefix)    Neg Not LogicNot
+    f(a, b)     (postfix)   call
+    e.name      (postfix)   field
+    s[i]        (postfix)   index, like SeqIndex
+    s[i..j]     (postfix)   slice; either bound can be left out
+
+Left associative operators group from the left, so `a - b - c` is `(a - b) - c`. Comparisons
+don't associate at all: `a < b < c` is reported as an error (ChainedComparison) instead of quietly
+comparing a bool with c, and parens must be used to say what's meant.
+
+A function without a name, `fn(a, b) { ... }`, is an operand like any other, so it can be called
+or passed along right where it's written. So are `(a, b)`, which makes a Tuple (`(a,)` has one
+item, `()` none, and `(a)` is just a), and `[a, b]`, which makes a List.
+
+The other UnaryOpTypes (IntToReal, Floor, Ceil, Trunc and Round) don't have operators. They're
+written like calls, `floor(x)`, and parse as ordinary calls, so they bind like postfix operators.
+*/
+use peanut_script_compiler::stage0::BinaryOpType;
+
+use super::{ParseError, ParseErrorKind, Parser};
+use crate::ast::{Expr, ExprKind, InterpolationPart, Span};
+use crate::lexer::{Keyword, Token, TokenItem};
+
+/// prefix operators bind tighter than every binary operator
+static PREFIX_PRECEDENCE: u8 = 10;
+static COMPARISON_PRECEDENCE: u8 = 3;
+
+fn precedence(op: BinaryOpType) -> u8 {
+    match op {
+        BinaryOpType::LogicOr => 1,
+        BinaryOpType::LogicAnd => 2,
+        BinaryOpType::Equal
+        | BinaryOpType::NotEqual
+        | BinaryOpType::Greater
+        | BinaryOpType::GreaterOrEqual
+        | BinaryOpType::Less
+        | BinaryOpType::LessOrEqual
+        | BinaryOpType::Identity => COMPARISON_PRECEDENCE,
+        BinaryOpType::Or => 4,
+        BinaryOpType::Xor => 5,
+        BinaryOpType::And => 6,
+        BinaryOpType::Shl | BinaryOpType::Shr => 7,
+        BinaryOpType::Add | BinaryOpType::Sub => 8,
+        BinaryOpType::Mul | BinaryOpType::Div | BinaryOpType::Rem => 9,
+    }
+}
+
+/// whether `token` can be the first token of an expression
+fn starts_expr(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::IntLiteral(_)
+            | Token::RealLiteral(_)
+            | Token::SingleQuoteString(_)
+            | Token::DoubleQuoteString(_)
+            | Token::ByteString(_)
+            | Token::InterpolatedStringStart
+            | Token::Identifier
+            | Token::LeftParen
+            | Token::LeftSquare
+            | Token::Keyword(Keyword::True | Keyword::False | Keyword::None | Keyword::Fn)
+    ) || token.unary_op().is_some()
+}
+
+fn token_span(t: &TokenItem) -> Span {
+    Span {
+        start: t.start,
+        len: t.len,
+    }
+}
+
+impl Parser<'_> {
+    pub(super) fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.parse_binary(0)
+    }
+
+    /// parses an expression made of operators with at least `min_precedence`
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let mut lhs = self.parse_prefix()?;
+        let mut compared = false;
+        while let Some(op) = self.peek().and_then(|t| t.binary_op()) {
+            let precedence = precedence(op);
+            if precedence < min_precedence {
+                break;
+            }
+            let op_token = self.bump();
+            if precedence == COMPARISON_PRECEDENCE {
+                if compared {
+                    return Err(ParseError {
+                        error_kind: ParseErrorKind::ChainedComparison,
+                        span: token_span(op_token),
+                    });
+                }
+                compared = true;
+            }
+            // the expression goes on after a line break, since it can't end
+            // with an operator
+            while self.peek_nth(0) == Some(&Token::LineFeed) {
+                self.pos += 1;
+            }
+            let rhs = self.parse_operand(precedence + 1, op_token)?;
+            lhs = Expr {
+                kind: ExprKind::Binary {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+                span: self.span_from(start),
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// parses the operand after `op_token`, which must be there
+    fn parse_operand(
+        &mut self,
+        min_precedence: u8,
+        op_token: &TokenItem,
+    ) -> Result<Expr, ParseError> {
+        if !self.peek().is_some_and(starts_expr) {
+            return Err(ParseError {
+                error_kind: ParseErrorKind::MissingOperand,
+                span: token_span(op_token),
+            });
+        }
+        self.parse_binary(min_precedence)
+    }
+
+    fn parse_prefix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let token = match self.peek() {
+            Some(token) => token,
+            None => return Err(self.unexpected("an expression")),
+        };
+        if let Some(op) = token.unary_op() {
+            let op_token = self.bump();
+            let expr = self.parse_operand(PREFIX_PRECEDENCE, op_token)?;
+            return Ok(Expr {
+                kind: ExprKind::Unary {
+                    op,
+                    expr: Box::new(expr),
+                },
+                span: self.span_from(start),
+            });
+        }
+        if token.binary_op().is_some() {
+            // like `* b`, where the left operand is missing
+            return Err(ParseError {
+                error_kind: ParseErrorKind::MissingOperand,
+                span: token_span(self.tokens[self.pos]),
+            });
+        }
+        self.parse_postfix()
+    }
+
+    /// a primary expression, followed by any number of calls, indexes,
+    /// slices and fields
+    pub(super) fn parse_postfix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let mut expr = self.parse_primary()?;
+        // a `(` or `[` on the next line starts a new statement, so line
+        // breaks aren't skipped here (unless inside parens already)
+        loop {
+            let kind = match self.peek() {
+                Some(Token::LeftParen) => ExprKind::Call {
+                    func: Box::new(expr),
+                    args: self.parse_args()?,
+                },
+                Some(Token::LeftSquare) => self.parse_index(expr)?,
+                Some(Token::Period) => {
+                    self.bump();
+                    ExprKind::Field {
+                        expr: Box::new(expr),
+                        name: self.ident("a field name")?,
+                    }
+                }
+                _ => break,
+            };
+            expr = Expr {
+                kind,
+                span: self.span_from(start),
+            };
+        }
+        Ok(expr)
+    }
+
+    /// parses `[index]` or `[start..end]` after `seq`
+    fn parse_index(&mut self, seq: Expr) -> Result<ExprKind, ParseError> {
+        self.bump();
+        self.nesting += 1;
+        let start = if self.peek() == Some(&Token::DoublePeriod) {
+            None
+        } else {
+            Some(Box::new(self.parse_expr()?))
+        };
+        let kind = match start {
+            Some(index) if !self.eat(&Token::DoublePeriod) => ExprKind::Index {
+                seq: Box::new(seq),
+                index,
+            },
+            start => {
+                if start.is_none() {
+                    self.bump();
+                }
+                let end = if self.peek() == Some(&Token::RightSquare) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expr()?))
+                };
+                ExprKind::Slice {
+                    seq: Box::new(seq),
+                    start,
+                    end,
+                }
+            }
+        };
+        self.expect(&Token::RightSquare, "`]`")?;
+        self.nesting -= 1;
+        Ok(kind)
+    }
+
+    fn parse_args(&mut self) -> Result<Vec<Expr>, ParseError> {
+        self.bump();
+        self.nesting += 1;
+        let mut args = Vec::new();
+        while self.peek() != Some(&Token::RightParen) {
+            args.push(self.parse_expr()?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen, "`,` or `)`")?;
+        self.nesting -= 1;
+        Ok(args)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let kind = match self.peek() {
+            Some(Token::IntLiteral(v)) => ExprKind::Int(*v),
+            Some(Token::RealLiteral(v)) => ExprKind::Real(*v),
+            Some(Token::Keyword(Keyword::True)) => ExprKind::Bool(true),
+            Some(Token::Keyword(Keyword::False)) => ExprKind::Bool(false),
+            Some(Token::Keyword(Keyword::None)) => ExprKind::None,
+            Some(Token::SingleQuoteString(s) | Token::DoubleQuoteString(s)) => {
+                ExprKind::Str(s.clone())
+            }
+            Some(Token::ByteString(s)) => ExprKind::Bytes(s.clone()),
+            Some(Token::Identifier) => {
+                let ident = self.ident("an expression")?;
+                return Ok(Expr {
+                    kind: ExprKind::Name(ident.name),
+                    span: ident.span,
+                });
+            }
+            Some(Token::InterpolatedStringStart) => {
+                let parts = self.parse_interpolated()?;
+                return Ok(Expr {
+                    kind: ExprKind::Interpolated(parts),
+                    span: self.span_from(start),
+                });
+            }
+            Some(Token::Keyword(Keyword::Fn)) => {
+                self.bump();
+                let params = self.parse_params()?;
+                let body = self.parse_block()?;
+                return Ok(Expr {
+                    kind: ExprKind::Fn { params, body },
+                    span: self.span_from(start),
+                });
+            }
+            Some(Token::LeftParen) => {
+                self.bump();
+                self.nesting += 1;
+                let mut items = Vec::new();
+                let mut comma = false;
+                while self.peek() != Some(&Token::RightParen) {
+                    items.push(self.parse_expr()?);
+                    comma = self.eat(&Token::Comma);
+                    if !comma {
+                        break;
+                    }
+                }
+                self.expect(&Token::RightParen, "`,` or `)`")?;
+                self.nesting -= 1;
+                let kind = if items.len() == 1 && !comma {
+                    // the parens are part of the expression's span
+                    items.pop().unwrap().kind
+                } else {
+                    ExprKind::Tuple(items)
+                };
+                return Ok(Expr {
+                    kind,
+                    span: self.span_from(start),
+                });
+            }
+            Some(Token::LeftSquare) => {
+                self.bump();
+                self.nesting += 1;
+                let mut items = Vec::new();
+                while self.peek() != Some(&Token::RightSquare) {
+                    items.push(self.parse_expr()?);
+                    if !self.eat(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.expect(&Token::RightSquare, "`,` or `]`")?;
+                self.nesting -= 1;
+                return Ok(Expr {
+                    kind: ExprKind::List(items),
+                    span: self.span_from(start),
+                });
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        self.bump();
+        Ok(Expr {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_interpolated(&mut self) -> Result<Vec<InterpolationPart>, ParseError> {
+        self.bump();
+        let mut parts = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::StringPart(text)) => {
+                    let t = self.bump();
+                    parts.push(InterpolationPart::Text(text.clone(), token_span(t)));
+                }
+                Some(Token::InterpolationStart) => {
+                    self.bump();
+                    self.nesting += 1;
+                    let expr = self.parse_expr()?;
+                    self.expect(&Token::InterpolationEnd, "`}`")?;
+                    self.nesting -= 1;
+                    parts.push(InterpolationPart::Expr(expr));
+                }
+                Some(Token::InterpolatedStringEnd) => {
+                    self.bump();
+                    return Ok(parts);
+                }
+                _ => return Err(self.unexpected("the end of the string")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::StatementKind;
+    use crate::lexer;
+    use crate::parser::parse;
+
+    /// parses `source` as a single expression statement
+    fn expr(source: &str) -> Result<Expr, ParseError> {
+        let ts = lexer::parse(source.as_bytes()).expect("the source should lex");
+        let mut module = parse(source.as_bytes(), &ts)?;
+        match module.body.pop().map(|s| s.kind) {
+            Some(StatementKind::Expr(expr)) => Ok(expr),
+            kind => panic!("{:?}", kind),
+        }
+    }
+
+    /// the expression with parens around every operator and its operands,
+    /// to show how it was grouped
+    fn grouped(source: &str) -> String {
+        fn show(source: &str, e: &Expr) -> String {
+            let text = |start: usize, end: usize| source[start..end].trim().to_string();
+            match &e.kind {
+                ExprKind::Binary { lhs, rhs, .. } => format!(
+                    "({} {} {})",
+                    show(source, lhs),
+                    text(lhs.span.end(), rhs.span.start),
+                    show(source, rhs)
+                ),
+                ExprKind::Unary { expr, .. } => {
+                    format!(
+                        "({}{})",
+                        text(e.span.start, expr.span.start),
+                        show(source, expr)
+                    )
+                }
+                _ => text(e.span.start, e.span.end()),
+            }
+        }
+        show(source, &expr(source).expect("the source should parse"))
+    }
+
+    fn error(source: &str) -> (ParseErrorKind, Span) {
+        let e = expr(source).expect_err("the source shouldn't parse");
+        (e.error_kind, e.span)
+    }
+
+    #[test]
+    fn operators_group_by_precedence() {
+        assert_eq!(
+            grouped("a || b && c == d | e ^ f & g << h + i * j"),
+            "(a || (b && (c == (d | (e ^ (f & (g << (h + (i * j)))))))))"
+        );
+        assert_eq!(
+            grouped("a * b + c << d & e ^ f | g === h && i || j"),
+            "(((((((((a * b) + c) << d) & e) ^ f) | g) === h) && i) || j)"
+        );
+        assert_eq!(grouped("a - b - c"), "((a - b) - c)");
+        // prefix operators bind tighter than binary ones, and looser than
+        // calls and indexing
+        assert_eq!(grouped("-a * ~b[1](c)"), "((-a) * (~b[1](c)))");
+    }
+
+    #[test]
+    fn comparisons_dont_chain() {
+        assert_eq!(
+            error("a < b < c"),
+            (ParseErrorKind::ChainedComparison, Span::new(6, 7))
+        );
+        assert_eq!(error("a == b != c").1, Span::new(7, 9));
+        assert_eq!(grouped("(a < b) == c"), "((a < b) == c)");
+    }
+
+    #[test]
+    fn missing_operands() {
+        for (source, at) in [("a +", 2), ("* b", 0), ("a * * b", 2)] {
+            assert_eq!(
+                error(source),
+                (ParseErrorKind::MissingOperand, Span::new(at, at + 1)),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn interpolated_text_has_the_span_of_its_token() {
+        let parts = match expr(r#""ab{x}c""#).map(|e| e.kind) {
+            Ok(ExprKind::Interpolated(parts)) => parts,
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(
+            parts[0],
+            InterpolationPart::Text(b"ab".to_vec(), Span::new(1, 3))
+        );
+        assert_eq!(
+            parts[2],
+            InterpolationPart::Text(b"c".to_vec(), Span::new(6, 7))
+        );
+    }
+}
@@ -0,0 +1,834 @@
+/*
+The parser turns a TokenStream into the surface AST (see ast.rs). It's a hand-written recursive
+descent parser, with one method per construct, and it looks at most a few tokens ahead.
+
+A source file is a list of statements, and so is every block:
+    fn name(a, b) { ... }       (without the name, `fn(a, b) { ... }` is an expression)
+    let pattern = value         (the value is optional if the pattern is a name)
+    const name = value
+    var name = value            (the value is optional)
+    import foo.bar as baz       (the `as baz` is optional)
+    record Name { a, b }
+    if a { ... } else if b { ... } else { ... }
+    loop { ... }
+    while condition { ... }
+    for pattern in seq { ... }
+    label: loop { ... }         (or `label: while`, or `label: for`)
+    break label                 (the label is optional, and so it is for continue)
+    continue label
+    return value                (the value is optional)
+    place = value               (or a compound assignment, like `place += value`)
+    pattern = value             (if the pattern starts with `(` or `[`)
+    expression                  (see expr.rs for operators and their precedence)
+
+Patterns take a value apart, in a `let`, a parameter or an assignment:
+    name                        the whole value; in an assignment, any place, like `a[i]`
+    _                           the value is thrown away
+    (a, b)                      a seq of exactly two items, matched by a and b. `(a,)` has one
+                                item, and `(a)` is just a
+    [a, b]                      the same as `(a, b)`
+    [a, ..rest, b]              a seq of at least two items; rest is a List of the ones between.
+                                `..` can be anywhere in the list, but only once, and without a
+                                name it keeps nothing
+Statements that start with `(` or `[` are parsed as a pattern first, and as an expression if they
+turn out not to be an assignment.
+
+Statements end at a line break or a `;`, or right before the `}` that closes their block. Inside
+parens and square brackets (and inside the braces of an interpolated string or a record) line breaks
+don't end anything, so a long call can be split over several lines. A block brings that back, even
+when it's inside parens. A line break right after a binary operator doesn't end the expression
+either, and an `else` may start on the line after the `}` it follows.
+
+Comments are skipped; the parser works the same on streams from parse and parse_lossless.
+
+parse_with_recovery keeps going after an error, so that a half-finished statement doesn't hide the
+problems in the rest of the file. The statement with the error is skipped up to where the next one
+could start: a line break or `;`, or the `}` that closes its block (braces and parens opened while
+skipping are skipped as a whole). In the tree, it's replaced with a StatementKind::Error that spans
+the skipped code. If only the value of a `let`, an assignment or a `return` is bad, only the value
+is replaced, with an ExprKind::Error, so later passes still see the name being bound. A block that
+is never closed ends at the end of the source. Unknown tokens were already reported by the lexer,
+so errors at them aren't reported again.
+*/
+mod expr;
+
+use crate::ast::{
+    Block, Expr, ExprKind, FnDef, For, Ident, If, IfElse, Import, Loop, Module, Pattern,
+    PatternKind, Record, Rest, Span, Statement, StatementKind,
+};
+use crate::lexer::{Keyword, Token, TokenItem, TokenStream};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub error_kind: ParseErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// a token that can't go here; `expected` says what could have
+    UnexpectedToken { expected: &'static str },
+    /// the source ended early; the span is empty, and at the end of the source
+    UnexpectedEnd { expected: &'static str },
+    /// the left side of an assignment isn't a name or an index
+    InvalidAssignTarget,
+    /// an operator with nothing on one side, like `a +` or `* b`; the span
+    /// is the operator
+    MissingOperand,
+    /// comparisons used like `a < b < c`; the span is the second operator.
+    /// they need parens to say what's meant
+    ChainedComparison,
+}
+
+/// parses the tokens lexed from `source` into a module, stopping at the
+/// first error
+pub fn parse(source: &[u8], ts: &TokenStream) -> Result<Module, ParseError> {
+    let (module, errors) = parse_with_recovery(source, ts);
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(module),
+    }
+}
+
+/// like parse, but keeps going after an error, so every problem in the source
+/// is reported at once. the module has error nodes where the errors were
+pub fn parse_with_recovery(source: &[u8], ts: &TokenStream) -> (Module, Vec<ParseError>) {
+    let mut p = Parser::new(source, ts);
+    let body = p.parse_statements(false);
+    let module = Module {
+        body,
+        span: Span::new(0, source.len()),
+    };
+    (module, p.errors)
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    /// the stream's tokens, without comments
+    tokens: Vec<&'a TokenItem>,
+    /// the index of the next token
+    pos: usize,
+    /// how many parens, brackets or interpolations the parser is inside of;
+    /// line breaks are skipped when this isn't zero
+    nesting: usize,
+    /// the end of the last token that was consumed
+    prev_end: usize,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a [u8], ts: &'a TokenStream) -> Self {
+        let tokens = ts
+            .tokens
+            .iter()
+            .filter(|t| !matches!(t.token, Token::LineComment | Token::BlockComment))
+            .collect();
+        Parser {
+            source,
+            tokens,
+            pos: 0,
+            nesting: 0,
+            prev_end: 0,
+            errors: vec![],
+        }
+    }
+
+    /// the next token, or None at the end of the source
+    fn peek(&mut self) -> Option<&'a Token> {
+        if self.nesting > 0 {
+            while self.peek_nth(0) == Some(&Token::LineFeed) {
+                self.pos += 1;
+            }
+        }
+        self.peek_nth(0)
+    }
+
+    /// the token `n` places after the next one, without skipping line breaks
+    fn peek_nth(&self, n: usize) -> Option<&'a Token> {
+        self.tokens.get(self.pos + n).map(|t| &t.token)
+    }
+
+    /// consumes the next token; there must be one
+    fn bump(&mut self) -> &'a TokenItem {
+        self.peek();
+        let t = self.tokens[self.pos];
+        self.pos += 1;
+        self.prev_end = t.start + t.len;
+        t
+    }
+
+    /// consumes the next token if it's `token`
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token, expected: &'static str) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    /// an error for the next token, which isn't what was `expected`
+    fn unexpected(&mut self, expected: &'static str) -> ParseError {
+        self.peek();
+        match self.tokens.get(self.pos) {
+            Some(t) => ParseError {
+                error_kind: ParseErrorKind::UnexpectedToken { expected },
+                span: Span {
+                    start: t.start,
+                    len: t.len,
+                },
+            },
+            None => ParseError {
+                error_kind: ParseErrorKind::UnexpectedEnd { expected },
+                span: Span {
+                    start: self.source.len(),
+                    len: 0,
+                },
+            },
+        }
+    }
+
+    fn report(&mut self, e: ParseError) {
+        let at = self.tokens.partition_point(|t| t.start < e.span.start);
+        let at_unknown = self.tokens.get(at).is_some_and(|t| {
+            t.token == Token::Unknown
+                && matches!(e.error_kind, ParseErrorKind::UnexpectedToken { .. })
+        });
+        // every unclosed block reports the end of the source; once is enough
+        if !at_unknown && self.errors.last() != Some(&e) {
+            self.errors.push(e);
+        }
+    }
+
+    /// skips to where the next statement could start: a line break or `;`,
+    /// or the `}` that closes the current block. braces and parens opened
+    /// along the way are skipped along with everything inside them
+    fn synchronize(&mut self) {
+        let mut braces = 0usize;
+        let mut parens = 0usize;
+        while let Some(token) = self.peek_nth(0) {
+            match token {
+                Token::LineFeed | Token::Semicolon if braces == 0 && parens == 0 => break,
+                Token::RightCurly if braces == 0 => break,
+                Token::LeftCurly => braces += 1,
+                Token::RightCurly => braces -= 1,
+                Token::LeftParen | Token::LeftSquare => parens += 1,
+                Token::RightParen | Token::RightSquare => parens = parens.saturating_sub(1),
+                _ => {}
+            }
+            self.prev_end = self.tokens[self.pos].start + self.tokens[self.pos].len;
+            self.pos += 1;
+        }
+    }
+
+    /// parses an expression, or reports the error, skips the rest of the
+    /// statement and stands in an ExprKind::Error for it
+    fn parse_expr_recovering(&mut self) -> Expr {
+        let start = self.start();
+        let nesting = self.nesting;
+        match self.parse_expr() {
+            Ok(expr) => expr,
+            Err(e) => {
+                self.report(e);
+                self.nesting = nesting;
+                self.synchronize();
+                Expr {
+                    kind: ExprKind::Error,
+                    span: self.span_from(start),
+                }
+            }
+        }
+    }
+
+    /// the offset where the next token starts
+    fn start(&mut self) -> usize {
+        self.peek();
+        match self.tokens.get(self.pos) {
+            Some(t) => t.start,
+            None => self.source.len(),
+        }
+    }
+
+    /// the span from `start` to the end of the last token that was consumed
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.prev_end.max(start))
+    }
+
+    fn ident(&mut self, expected: &'static str) -> Result<Ident, ParseError> {
+        if self.peek() != Some(&Token::Identifier) {
+            return Err(self.unexpected(expected));
+        }
+        let t = self.bump();
+        let text = &self.source[t.start..t.start + t.len];
+        Ok(Ident {
+            name: String::from_utf8_lossy(text).into_owned(),
+            span: Span {
+                start: t.start,
+                len: t.len,
+            },
+        })
+    }
+
+    /// parses statements up to the end of the source, or up to the `}` that
+    /// closes the current block if `in_block`
+    /// (the `}` is left for the caller)
+    fn parse_statements(&mut self, in_block: bool) -> Vec<Statement> {
+        let mut body = Vec::new();
+        loop {
+            while self.eat(&Token::LineFeed) || self.eat(&Token::Semicolon) {}
+            match self.peek() {
+                None => break,
+                Some(Token::RightCurly) if in_block => break,
+                Some(Token::RightCurly) => {
+                    // there's no block for it to close
+                    let e = self.unexpected("a statement");
+                    self.report(e);
+                    self.bump();
+                    continue;
+                }
+                _ => {}
+            }
+            let start = self.start();
+            let pos = self.pos;
+            match self.parse_statement() {
+                Ok(statement) => body.push(statement),
+                Err(e) => {
+                    self.report(e);
+                    self.nesting = 0;
+                    // the statement might not have gotten past its first
+                    // token, like a stray `)`
+                    if self.pos == pos {
+                        self.bump();
+                    }
+                    self.synchronize();
+                    body.push(Statement {
+                        kind: StatementKind::Error,
+                        span: self.span_from(start),
+                    });
+                }
+            }
+            match self.peek() {
+                None | Some(Token::RightCurly) => {}
+                Some(Token::LineFeed) | Some(Token::Semicolon) => {
+                    self.bump();
+                }
+                _ => {
+                    let e = self.unexpected("end of statement");
+                    self.report(e);
+                    self.synchronize();
+                }
+            }
+        }
+        body
+    }
+
+    fn parse_block(&mut self) -> Result<Block, ParseError> {
+        let start = self.start();
+        self.expect(&Token::LeftCurly, "`{`")?;
+        // statements end at line breaks again, even inside parens
+        let nesting = std::mem::replace(&mut self.nesting, 0);
+        let body = self.parse_statements(true);
+        self.nesting = nesting;
+        // an unclosed block runs to the end of the source; the error is
+        // reported, but the statements in it are kept
+        if !self.eat(&Token::RightCurly) {
+            let e = self.unexpected("`}`");
+            self.report(e);
+        }
+        Ok(Block {
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.start();
+        let kind = match self.peek() {
+            // `fn(` starts a function expression
+            Some(Token::Keyword(Keyword::Fn)) if self.peek_nth(1) != Some(&Token::LeftParen) => {
+                StatementKind::Fn(self.parse_fn()?)
+            }
+            Some(Token::Keyword(Keyword::Let)) => {
+                self.bump();
+                let pattern = self.parse_pattern(false)?;
+                // a pattern that takes a value apart needs a value
+                if !matches!(pattern.kind, PatternKind::Place(_))
+                    && self.peek() != Some(&Token::Equal)
+                {
+                    return Err(self.unexpected("`=`"));
+                }
+                let value = self.parse_value();
+                StatementKind::Let { pattern, value }
+            }
+            Some(Token::Keyword(Keyword::Var)) => {
+                self.bump();
+                let name = self.ident("a name")?;
+                let value = self.parse_value();
+                StatementKind::Var { name, value }
+            }
+            Some(Token::Keyword(Keyword::Const)) => {
+                self.bump();
+                let name = self.ident("a name")?;
+                self.expect(&Token::Equal, "`=`")?;
+                let value = self.parse_expr_recovering();
+                StatementKind::Const { name, value }
+            }
+            Some(Token::Keyword(Keyword::Import)) => StatementKind::Import(self.parse_import()?),
+            Some(Token::Keyword(Keyword::Record)) => StatementKind::Record(self.parse_record()?),
+            Some(Token::Keyword(Keyword::If)) => StatementKind::IfElse(self.parse_if_else()?),
+            Some(Token::Keyword(Keyword::Loop | Keyword::While)) => {
+                StatementKind::Loop(self.parse_loop(None)?)
+            }
+            Some(Token::Keyword(Keyword::For)) => StatementKind::For(self.parse_for(None)?),
+            Some(Token::Identifier) if self.at_label() => {
+                let label = self.ident("a label")?;
+                self.bump();
+                if self.peek() == Some(&Token::Keyword(Keyword::For)) {
+                    StatementKind::For(self.parse_for(Some(label))?)
+                } else {
+                    StatementKind::Loop(self.parse_loop(Some(label))?)
+                }
+            }
+            Some(Token::Keyword(keyword @ (Keyword::Break | Keyword::Continue))) => {
+                self.bump();
+                let label = if self.peek() == Some(&Token::Identifier) {
+                    Some(self.ident("a label")?)
+                } else {
+                    None
+                };
+                if *keyword == Keyword::Break {
+                    StatementKind::Break { label }
+                } else {
+                    StatementKind::Continue { label }
+                }
+            }
+            Some(Token::Keyword(Keyword::Return)) => {
+                self.bump();
+                let value = match self.peek() {
+                    None | Some(Token::LineFeed | Token::Semicolon | Token::RightCurly) => None,
+                    _ => Some(self.parse_expr_recovering()),
+                };
+                StatementKind::Return(value)
+            }
+            Some(Token::LeftParen | Token::LeftSquare) => match self.parse_assign_pattern() {
+                Some(kind) => kind,
+                None => self.parse_expr_statement()?,
+            },
+            _ => self.parse_expr_statement()?,
+        };
+        Ok(Statement {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    /// parses an optional `= value`
+    fn parse_value(&mut self) -> Option<Expr> {
+        if self.eat(&Token::Equal) {
+            Some(self.parse_expr_recovering())
+        } else {
+            None
+        }
+    }
+
+    /// parses `pattern = value`, if that's what the statement is. if it
+    /// isn't, nothing is consumed, and it's parsed again as an expression
+    fn parse_assign_pattern(&mut self) -> Option<StatementKind> {
+        let (pos, prev_end, nesting) = (self.pos, self.prev_end, self.nesting);
+        let errors = self.errors.len();
+        if let Ok(pattern) = self.parse_pattern(true) {
+            if self.eat(&Token::Equal) {
+                let value = self.parse_expr_recovering();
+                return Some(StatementKind::AssignPattern { pattern, value });
+            }
+        }
+        self.pos = pos;
+        self.prev_end = prev_end;
+        self.nesting = nesting;
+        self.errors.truncate(errors);
+        None
+    }
+
+    /// parses a pattern. in an assignment, its names can be any place
+    fn parse_pattern(&mut self, assign: bool) -> Result<Pattern, ParseError> {
+        let start = self.start();
+        let kind = match self.peek() {
+            Some(Token::LeftParen) => {
+                self.bump();
+                self.nesting += 1;
+                let mut items = Vec::new();
+                let mut comma = false;
+                while self.peek() != Some(&Token::RightParen) {
+                    items.push(self.parse_pattern(assign)?);
+                    comma = self.eat(&Token::Comma);
+                    if !comma {
+                        break;
+                    }
+                }
+                self.expect(&Token::RightParen, "`,` or `)`")?;
+                self.nesting -= 1;
+                if items.len() == 1 && !comma {
+                    // just parens around a pattern
+                    let kind = items.pop().unwrap().kind;
+                    return Ok(Pattern {
+                        kind,
+                        span: self.span_from(start),
+                    });
+                }
+                PatternKind::Tuple(items)
+            }
+            Some(Token::LeftSquare) => {
+                self.bump();
+                self.nesting += 1;
+                let mut items = Vec::new();
+                let mut rest = None;
+                while self.peek() != Some(&Token::RightSquare) {
+                    if self.peek() == Some(&Token::DoublePeriod) {
+                        if rest.is_some() {
+                            return Err(self.unexpected("a pattern, since `..` is already used"));
+                        }
+                        let start = self.start();
+                        self.bump();
+                        let place = match self.peek() {
+                            Some(Token::Comma | Token::RightSquare) => None,
+                            _ => Some(self.parse_place(assign)?),
+                        };
+                        rest = Some(Rest {
+                            index: items.len(),
+                            place,
+                            span: self.span_from(start),
+                        });
+                    } else {
+                        items.push(self.parse_pattern(assign)?);
+                    }
+                    if !self.eat(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.expect(&Token::RightSquare, "`,` or `]`")?;
+                self.nesting -= 1;
+                PatternKind::List { items, rest }
+            }
+            Some(Token::Underscore) => {
+                self.bump();
+                PatternKind::Wildcard
+            }
+            _ => PatternKind::Place(self.parse_place(assign)?),
+        };
+        Ok(Pattern {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    /// a name, or in an assignment any place
+    fn parse_place(&mut self, assign: bool) -> Result<Expr, ParseError> {
+        if !assign {
+            let name = self.ident("a name or a pattern")?;
+            return Ok(Expr {
+                kind: ExprKind::Name(name.name),
+                span: name.span,
+            });
+        }
+        let expr = self.parse_postfix()?;
+        if !expr.is_place() {
+            return Err(ParseError {
+                error_kind: ParseErrorKind::InvalidAssignTarget,
+                span: expr.span,
+            });
+        }
+        Ok(expr)
+    }
+
+    /// an expression on its own, or the left side of an assignment
+    fn parse_expr_statement(&mut self) -> Result<StatementKind, ParseError> {
+        let expr = self.parse_expr()?;
+        let op = match self.peek() {
+            Some(Token::Equal) => None,
+            Some(token) => match token.compound_assign_op() {
+                Some(op) => Some(op),
+                None => return Ok(StatementKind::Expr(expr)),
+            },
+            None => return Ok(StatementKind::Expr(expr)),
+        };
+        if !expr.is_place() {
+            return Err(ParseError {
+                error_kind: ParseErrorKind::InvalidAssignTarget,
+                span: expr.span,
+            });
+        }
+        self.bump();
+        let value = self.parse_expr_recovering();
+        Ok(StatementKind::Assign {
+            place: expr,
+            op,
+            value,
+        })
+    }
+
+    /// whether the next tokens are `label: loop`, `label: while` or
+    /// `label: for`
+    fn at_label(&self) -> bool {
+        self.peek_nth(1) == Some(&Token::Colon)
+            && matches!(
+                self.peek_nth(2),
+                Some(Token::Keyword(
+                    Keyword::Loop | Keyword::While | Keyword::For
+                ))
+            )
+    }
+
+    fn parse_fn(&mut self) -> Result<FnDef, ParseError> {
+        self.bump();
+        let name = self.ident("a function name")?;
+        let params = self.parse_params()?;
+        let body = self.parse_block()?;
+        Ok(FnDef { name, params, body })
+    }
+
+    fn parse_params(&mut self) -> Result<Vec<Pattern>, ParseError> {
+        self.expect(&Token::LeftParen, "`(`")?;
+        self.nesting += 1;
+        let mut params = Vec::new();
+        while self.peek() != Some(&Token::RightParen) {
+            params.push(self.parse_pattern(false)?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen, "`,` or `)`")?;
+        self.nesting -= 1;
+        Ok(params)
+    }
+
+    fn parse_import(&mut self) -> Result<Import, ParseError> {
+        self.bump();
+        let mut path = vec![self.ident("a module name")?];
+        while self.eat(&Token::Period) {
+            path.push(self.ident("a module name")?);
+        }
+        let alias = if self.eat(&Token::Keyword(Keyword::As)) {
+            Some(self.ident("a name")?)
+        } else {
+            None
+        };
+        Ok(Import { path, alias })
+    }
+
+    fn parse_record(&mut self) -> Result<Record, ParseError> {
+        self.bump();
+        let name = self.ident("a record name")?;
+        self.expect(&Token::LeftCurly, "`{`")?;
+        self.nesting += 1;
+        let mut fields = Vec::new();
+        while self.peek() != Some(&Token::RightCurly) {
+            fields.push(self.ident("a field name")?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightCurly, "`,` or `}`")?;
+        self.nesting -= 1;
+        Ok(Record { name, fields })
+    }
+
+    fn parse_if_else(&mut self) -> Result<IfElse, ParseError> {
+        let if_ = self.parse_if()?;
+        let mut else_if = Vec::new();
+        let mut else_ = None;
+        while self.eat_else() {
+            if self.peek() == Some(&Token::Keyword(Keyword::If)) {
+                else_if.push(self.parse_if()?);
+            } else {
+                else_ = Some(self.parse_block()?);
+                break;
+            }
+        }
+        Ok(IfElse {
+            if_,
+            else_if,
+            else_,
+        })
+    }
+
+    fn parse_if(&mut self) -> Result<If, ParseError> {
+        let start = self.start();
+        self.bump();
+        let condition = self.parse_expr()?;
+        let body = self.parse_block()?;
+        Ok(If {
+            condition,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    /// consumes an `else`, even if it's on a later line
+    fn eat_else(&mut self) -> bool {
+        let mut n = 0;
+        while self.peek_nth(n) == Some(&Token::LineFeed) {
+            n += 1;
+        }
+        if self.peek_nth(n) != Some(&Token::Keyword(Keyword::Else)) {
+            return false;
+        }
+        self.pos += n;
+        self.bump();
+        true
+    }
+
+    fn parse_loop(&mut self, label: Option<Ident>) -> Result<Loop, ParseError> {
+        let condition = if self.bump().token == Token::Keyword(Keyword::While) {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        let body = self.parse_block()?;
+        Ok(Loop {
+            label,
+            condition,
+            body,
+        })
+    }
+
+    fn parse_for(&mut self, label: Option<Ident>) -> Result<For, ParseError> {
+        self.bump();
+        let pattern = self.parse_pattern(false)?;
+        self.expect(&Token::Keyword(Keyword::In), "`in`")?;
+        let seq = self.parse_expr()?;
+        let body = self.parse_block()?;
+        Ok(For {
+            label,
+            pattern,
+            seq,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use peanut_script_compiler::stage0::BinaryOpType;
+
+    use super::*;
+    use crate::ast::ExprKind;
+    use crate::lexer;
+
+    fn module(source: &str) -> Module {
+        let ts = lexer::parse(source.as_bytes()).expect("the source should lex");
+        parse(source.as_bytes(), &ts).expect("the source should parse")
+    }
+
+    #[test]
+    fn statements_and_their_spans() {
+        let source =
+            "fn f(a) {\n  outer: loop { break outer }\n  return a\n}\nlet x = f(1); x += 2\n";
+        let body = module(source).body;
+        assert_eq!(body.len(), 3);
+        let f = match &body[0].kind {
+            StatementKind::Fn(f) => f,
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(f.name.name, "f");
+        assert_eq!(body[0].span, Span::new(0, 52));
+        let looped = match &f.body.body[0].kind {
+            StatementKind::Loop(l) => l,
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(looped.label.as_ref().unwrap().name, "outer");
+        assert!(matches!(
+            &looped.body.body[0].kind,
+            StatementKind::Break { label: Some(l) } if l.name == "outer"
+        ));
+        assert!(matches!(
+            f.body.body[1].kind,
+            StatementKind::Return(Some(_))
+        ));
+        let (pattern, value) = match &body[1].kind {
+            StatementKind::Let {
+                pattern,
+                value: Some(value),
+            } => (pattern, value),
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(pattern.span, Span::new(57, 58));
+        assert!(matches!(value.kind, ExprKind::Call { .. }));
+        assert_eq!(body[1].span, Span::new(53, 65));
+        assert!(matches!(
+            body[2].kind,
+            StatementKind::Assign {
+                op: Some(BinaryOpType::Add),
+                ..
+            }
+        ));
+        assert_eq!(body[2].span, Span::new(67, 73));
+    }
+
+    #[test]
+    fn if_else_chains() {
+        let body = module("if a { 1 }\nelse if b { 2 } else { 3 }").body;
+        let chain = match &body[0].kind {
+            StatementKind::IfElse(chain) => chain,
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(chain.else_if.len(), 1);
+        assert_eq!(chain.else_if[0].span, Span::new(16, 26));
+        assert!(chain.else_.is_some());
+    }
+
+    #[test]
+    fn recovery_reports_every_error() {
+        let source = "let a = * 2\nb = 1\n3 = b\nc = \x07\nfn f() { ) }\nreturn +";
+        let (ts, lex_errors) = lexer::parse_with_recovery(source.as_bytes());
+        assert_eq!(lex_errors.len(), 1);
+        let (module, errors) = parse_with_recovery(source.as_bytes(), &ts);
+        // the unknown token was reported by the lexer, so it isn't again
+        let errors: Vec<_> = errors.into_iter().map(|e| (e.error_kind, e.span)).collect();
+        assert_eq!(
+            errors,
+            [
+                (ParseErrorKind::MissingOperand, Span::new(8, 9)),
+                (ParseErrorKind::InvalidAssignTarget, Span::new(18, 19)),
+                (
+                    ParseErrorKind::UnexpectedToken {
+                        expected: "an expression"
+                    },
+                    Span::new(39, 40)
+                ),
+                (ParseErrorKind::MissingOperand, Span::new(50, 51)),
+            ]
+        );
+        let body = &module.body;
+        assert_eq!(body.len(), 6);
+        // only the bad value of a let, an assignment or a return is replaced,
+        // so the statement itself is still there
+        assert!(matches!(
+            &body[0].kind,
+            StatementKind::Let { value: Some(v), .. } if v.kind == ExprKind::Error
+        ));
+        assert!(matches!(body[1].kind, StatementKind::Assign { .. }));
+        assert_eq!(body[2].kind, StatementKind::Error);
+        assert_eq!(body[2].span, Span::new(18, 23));
+        assert!(matches!(
+            &body[3].kind,
+            StatementKind::Assign { value, .. } if value.kind == ExprKind::Error
+        ));
+        let f = match &body[4].kind {
+            StatementKind::Fn(f) => f,
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(f.body.body[0].kind, StatementKind::Error);
+        assert!(matches!(
+            &body[5].kind,
+            StatementKind::Return(Some(v)) if v.kind == ExprKind::Error
+        ));
+    }
+}
@@ -0,0 +1,1869 @@
+/*
+The resolver lowers the surface AST into the stage0 tree. Along the way it looks up every name, so
+that each variable becomes a stage0 Var and each function a module item, and it reports the names
+that don't make sense, at the span they were written at.
+
+A source file becomes one stage0::Module. Item 0 is the main function, made of the top-level
+statements. The top-level `fn`s, `const`s, `var`s and `record`s come next, in source order, and then
+the other functions and the string literals: a Str for each string, and a Buffer for each byte
+string. An interpolated string is its parts joined with Add, and each expression in it goes through
+StrFromValue first, so `"x is {x}"` is `"x is " + StrFromValue(x)`. Code reaches items through the
+module tuple, which every function keeps in local 0 (ExprKind::ModuleRef): reading an item is a
+SeqGet on it, and assigning to a `var` is a SeqSet.
+
+A `const` whose value is a literal (or a negated number) is in its item as soon as the module is
+loaded. Any other `const`, and every `var`, starts out as none and is set when the main function
+gets to it, so a function that reads it before then sees none. `const`, `var` and `record` can only
+be written at the top level.
+
+Scopes:
+    - A block is a scope, and a function's parameters are in the scope of its body.
+    - `let` binds a new variable, from the statement after it to the end of the block. Binding a
+      name that's already in scope shadows it, even in the same block, and `let x = x + 1` reads
+      the x from before.
+    - The top-level `fn`s, `const`s, `var`s and `record`s are in scope in the whole module, so
+      functions can use each other and the module's state no matter which is written first. Two of
+      them can't have the same name, and neither can two parameters or two fields of a record.
+    - A `fn` in any other block is a closure, and it's bound like a `let`: from its own body on.
+    - A name that isn't bound can still be a builtin. Most take one argument:
+        real(x)     IntToReal
+        floor(x)    Floor, and ceil, trunc and round are the same
+        len(s)      SeqLen
+        table(l)    TableCreate, from a List of (key, value) Tuples
+        str(b)      StrFromBuffer, which fails if the Buffer isn't valid utf-8
+        bytes(s)    StrToBuffer
+        map(l)      MapCreate, from a List of (key, value) Tuples
+        keys(m)     MapKeys
+      and these take two:
+        find(s, needle)     StrFind
+        remove(m, key)      MapRemove
+        contains(m, key)    MapContains
+      Binding the name hides the builtin.
+
+Closures, both nested `fn`s and `fn(a, b) { ... }` expressions, can use the variables of the
+functions around them. They capture them by reference: a captured variable is kept in a cell
+(see the vm's closure ops), which the function that binds it and every closure that captured it
+share, so a write through any of them is seen by all, even after the function that bound it has
+returned. Each time a `let` runs it binds a new cell, so closures made in different iterations of
+a loop don't share a variable bound inside it. When a closure is nested in another, the one in
+between captures the variable too, to pass it on. A closure that captures nothing is just the
+function. Top-level `fn`s are items and aren't created at runtime, so they can't capture the main
+function's variables; state they share with it goes in a `var`.
+
+An `import` binds a name to another module, and the module's top-level items are its fields:
+`import foo.bar as baz` makes `baz.f()` call foo.bar's f. The loader (see loader.rs) finds the
+module and resolves it first, and hands its index in the program and its exports to resolve. The
+import compiles to a ModuleRef item, which the vm points at the other module's tuple when the
+program is loaded, and `baz.f` is a SeqGet on that tuple with the index of f, found at compile time.
+An imported `var` can be assigned to, like a local one. Imports aren't exported themselves, and
+neither are records.
+
+`record Point { x, y }` makes a RecordType item (see the vm's record ops), and `Point(1, 2)` creates
+a record: the Tuple (the item, 1, 2). A field of a value that isn't a module is a field of a
+record. When the resolver knows that the value is a Point, `p.x` is a SeqGet at the index of x, and
+a field that Point doesn't have is an error. When it doesn't, `p.x` is a RecordGet, which looks the
+field up by name when it runs. What the resolver knows is a stage1 Type: a call to a record's name
+is that record, and so is a variable that a `let` binds to one, unless the function assigns to
+that name anywhere (in a nested closure too), since the variable could then hold anything by the
+time the field is read. Everything else is Type::Unknown.
+
+A `for` loop is a Loop whose condition is IterNext on a state tuple (see the vm's iter.rs, which
+also says what happens when the value changes during the loop). `for x in s { ... }` is
+    let t = (s, none, none)
+    while IterNext(t) { let x = t[2]; ... }
+so `continue` goes on to the next item, and like a `let` in the body, the pattern binds new
+variables in every iteration.
+
+Labels are looked up the same way among the loops around a `break` or `continue`, innermost
+first. Every loop gets a stage0 label id, so `break` always says which loop it leaves.
+
+Patterns are lowered to a temporary variable for each tuple or list in them. `let (a, [b, ..c]) = v`
+is
+    let t = v, after checking that it has 2 items (SeqCheckLen)
+    let a = t[0]
+    let u = t[1], after checking that it has at least 1 item
+    let b = u[0]
+    let c = u[1..len(u)]
+An item after a rest is indexed from the end, like `u[len(u) - 1]`, and the rest is a ListGetSlice,
+so it needs a List. The value is taken apart after it's computed, so names are bound after the
+whole value is, and `(a, b) = (b, a)` swaps. A parameter that's a pattern is an argument with no
+name, which the function takes apart before its body runs.
+
+A compound assignment to an indexed place computes the seq and the index once, into temporaries:
+`a[i] += x` is `let s = a; let j = i; s[j] = s[j] + x`. Slices compile to SeqGetSlice,
+which works on Lists, Buffers and Strs, since the resolver doesn't know what type a value is,
+and a slice with no end passes none as its end, which SeqGetSlice takes as the seq's length.
+*/
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use peanut_script_compiler::stage0::{
+    self, BinaryOp, BinaryOpType, ModuleItem, SourceSpan, UnaryOp, UnaryOpType, Var,
+};
+use peanut_script_compiler::stage1::{RecordType, Type};
+use peanut_script_vm::bytecode::ops::LiteralValue;
+
+use crate::ast::{self, Block, Ident, InterpolationPart, Pattern, PatternKind, Span};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveError {
+    pub error_kind: ResolveErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveErrorKind {
+    /// a name that isn't bound where it's used
+    Undefined,
+    /// a second top-level item with the same name, or a name that's bound
+    /// twice by the parameters of a function or by one pattern; `first` is
+    /// where the name was first bound
+    Duplicate { first: Span },
+    /// a variable of the main function, used in a top-level function
+    Captured,
+    /// an assignment to the name of a function or a `const`
+    NotAssignable,
+    /// a `const`, `var` or `import` that isn't at the top level
+    NotTopLevel,
+    /// an import of a module that isn't in the imports given to resolve; the
+    /// span is the module's path
+    UnresolvedImport,
+    /// a field of an imported module that the module doesn't have; the span
+    /// is the field's name
+    UndefinedMember,
+    /// a `break` or `continue` outside of any loop
+    OutsideLoop,
+    /// a `break` or `continue` label that none of the loops around it have;
+    /// the span is the label
+    UndefinedLabel,
+    /// a builtin called with the wrong number of arguments; the span is the
+    /// call
+    BuiltinArgs { expected: usize },
+    /// a record created with the wrong number of fields; the span is the
+    /// call
+    RecordArgs { expected: usize },
+    /// a field that a record's type doesn't have; the span is the field's
+    /// name
+    UndefinedField,
+    /// something that parses, but can't be compiled yet
+    Unsupported { what: &'static str },
+}
+
+#[derive(Clone, Copy)]
+enum Builtin {
+    Unary(UnaryOpType),
+    Len,
+    Table,
+    Str,
+    Bytes,
+    Find,
+    Map,
+    Remove,
+    Contains,
+    Keys,
+}
+
+impl Builtin {
+    /// the number of arguments it takes
+    fn args(self) -> usize {
+        match self {
+            Builtin::Find | Builtin::Remove | Builtin::Contains => 2,
+            _ => 1,
+        }
+    }
+}
+
+static BUILTINS: [(&str, Builtin); 14] = [
+    ("real", Builtin::Unary(UnaryOpType::IntToReal)),
+    ("floor", Builtin::Unary(UnaryOpType::Floor)),
+    ("ceil", Builtin::Unary(UnaryOpType::Ceil)),
+    ("trunc", Builtin::Unary(UnaryOpType::Trunc)),
+    ("round", Builtin::Unary(UnaryOpType::Round)),
+    ("len", Builtin::Len),
+    ("table", Builtin::Table),
+    ("str", Builtin::Str),
+    ("bytes", Builtin::Bytes),
+    ("find", Builtin::Find),
+    ("map", Builtin::Map),
+    ("remove", Builtin::Remove),
+    ("contains", Builtin::Contains),
+    ("keys", Builtin::Keys),
+];
+
+/// a top-level item of a module, which other modules can import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Export {
+    /// the index of the item in the module
+    pub item: usize,
+    /// whether the item is a `var`
+    pub assignable: bool,
+}
+
+/// a module's exports, by name
+pub type Exports = HashMap<String, Export>;
+
+/// a module that an import refers to
+#[derive(Debug, Clone)]
+pub struct Imported {
+    /// the index of the module in the program
+    pub module: u32,
+    pub exports: Exports,
+}
+
+/// resolves the names in `module` and lowers it to stage0. `imports` has the
+/// modules that its imports refer to, by their dotted path, like "foo.bar".
+/// every error in the module is reported, not just the first
+pub fn resolve(
+    module: &ast::Module,
+    imports: &HashMap<String, Imported>,
+) -> Result<(stage0::Module, Exports), Vec<ResolveError>> {
+    let mut r = Resolver {
+        items: vec![None],
+        scopes: Vec::new(),
+        fns: Vec::new(),
+        modules: Vec::new(),
+        records: Vec::new(),
+        var_types: HashMap::new(),
+        strs: HashMap::new(),
+        buffers: HashMap::new(),
+        next_var: 0,
+        errors: Vec::new(),
+    };
+    let (args, _) = r.enter_fn(&[], &module.body, false);
+    // the module's items are bound before anything else, so they're in scope
+    // everywhere
+    let mut seen = HashMap::new();
+    let mut module_items = Vec::new();
+    let mut exports = Exports::new();
+    for statement in &module.body {
+        if let ast::StatementKind::Import(import) = &statement.kind {
+            let name = import.name();
+            let item = r.items.len();
+            let binding = match imports.get(&import.dotted_path()) {
+                Some(imported) => {
+                    r.items.push(Some(ModuleItem::ModuleRef(imported.module)));
+                    r.modules.push(imported.exports.clone());
+                    Binding::Module {
+                        item,
+                        exports: r.modules.len() - 1,
+                    }
+                }
+                None => {
+                    r.error(ResolveErrorKind::UnresolvedImport, import.path_span());
+                    r.items
+                        .push(Some(ModuleItem::LiteralValue(LiteralValue::None)));
+                    Binding::Item {
+                        item,
+                        assignable: false,
+                    }
+                }
+            };
+            match seen.get(&name.name) {
+                Some(&first) => r.error(ResolveErrorKind::Duplicate { first }, name.span),
+                None => {
+                    seen.insert(&name.name, name.span);
+                }
+            }
+            r.scopes
+                .last_mut()
+                .unwrap()
+                .insert(name.name.clone(), binding);
+            module_items.push(item);
+            continue;
+        }
+        if let ast::StatementKind::Record(record) = &statement.kind {
+            let item = r.items.len();
+            let record_type = r.record_item(record);
+            r.items.push(Some(record_type));
+            match seen.get(&record.name.name) {
+                Some(&first) => r.error(ResolveErrorKind::Duplicate { first }, record.name.span),
+                None => {
+                    seen.insert(&record.name.name, record.name.span);
+                }
+            }
+            let binding = Binding::Record {
+                item,
+                record: r.records.len() - 1,
+            };
+            r.scopes
+                .last_mut()
+                .unwrap()
+                .insert(record.name.name.clone(), binding);
+            module_items.push(item);
+            continue;
+        }
+        let (name, item, assignable) = match &statement.kind {
+            ast::StatementKind::Fn(def) => (&def.name, None, false),
+            ast::StatementKind::Const { name, value } => {
+                let item =
+                    literal_item(value).unwrap_or(ModuleItem::LiteralValue(LiteralValue::None));
+                (name, Some(item), false)
+            }
+            ast::StatementKind::Var { name, .. } => (
+                name,
+                Some(ModuleItem::LiteralValue(LiteralValue::None)),
+                true,
+            ),
+            _ => continue,
+        };
+        let index = r.items.len();
+        match seen.get(&name.name) {
+            Some(&first) => r.error(ResolveErrorKind::Duplicate { first }, name.span),
+            None => {
+                seen.insert(&name.name, name.span);
+                let export = Export {
+                    item: index,
+                    assignable,
+                };
+                exports.insert(name.name.clone(), export);
+            }
+        }
+        r.items.push(item);
+        let binding = Binding::Item {
+            item: index,
+            assignable,
+        };
+        r.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.name.clone(), binding);
+        module_items.push(index);
+    }
+    let mut module_items = module_items.into_iter();
+    let mut body = Vec::new();
+    for statement in &module.body {
+        match &statement.kind {
+            ast::StatementKind::Fn(def) => {
+                let item = module_items.next().unwrap();
+                let (args, mut body) = r.enter_fn(&def.params, &def.body.body, false);
+                body.extend(r.lower_statements(&def.body.body));
+                let f = r.exit_fn(args, body);
+                r.items[item] = Some(ModuleItem::Function(f));
+            }
+            ast::StatementKind::Const { name, value } => {
+                let item = module_items.next().unwrap();
+                if literal_item(value).is_none() {
+                    r.init_item(item, name, value, statement.span, &mut body);
+                }
+            }
+            ast::StatementKind::Var { name, value } => {
+                let item = module_items.next().unwrap();
+                if let Some(value) = value {
+                    r.init_item(item, name, value, statement.span, &mut body);
+                }
+            }
+            ast::StatementKind::Import(_) | ast::StatementKind::Record(_) => {
+                module_items.next();
+            }
+            _ => r.lower_statement(statement, &mut body),
+        }
+    }
+    let main = r.exit_fn(args, body);
+    r.items[0] = Some(ModuleItem::Function(main));
+    if !r.errors.is_empty() {
+        return Err(r.errors);
+    }
+    let items = r
+        .items
+        .into_iter()
+        .map(|item| item.expect("every function item is lowered"))
+        .collect();
+    Ok((stage0::Module { items }, exports))
+}
+
+#[derive(Clone, Copy)]
+enum Binding {
+    /// a variable of the function `depth` functions deep; main is 0
+    Var { var: Var, depth: usize },
+    /// a top-level `fn`, `const` or `var`; only a `var` is assignable
+    Item { item: usize, assignable: bool },
+    /// an imported module, whose exports are Resolver::modules[exports]
+    Module { item: usize, exports: usize },
+    /// a `record`, whose type is Resolver::records[record]; the item is the
+    /// vm's RecordType
+    Record { item: usize, record: usize },
+}
+
+/// the state of a function that's being lowered
+struct FnScope {
+    /// whether the function is a closure, which can capture variables
+    closure: bool,
+    /// the loops around the current statement, innermost last, with their
+    /// labels and stage0 label ids
+    loops: Vec<(Option<String>, usize)>,
+    next_label: usize,
+    /// see stage0::Function
+    captures: Vec<Var>,
+    cells: Vec<Var>,
+    /// the names that the function assigns to, anywhere in its body
+    assigned: HashSet<String>,
+}
+
+struct Resolver {
+    /// the module's items; functions are None until they're lowered
+    items: Vec<Option<ModuleItem>>,
+    /// the scopes that are open, innermost last
+    scopes: Vec<HashMap<String, Binding>>,
+    /// the functions that are being lowered, innermost last
+    fns: Vec<FnScope>,
+    /// the exports of the imported modules
+    modules: Vec<Exports>,
+    /// the types of the module's records
+    records: Vec<Rc<RecordType>>,
+    /// the types of the variables whose type is known
+    var_types: HashMap<Var, Type>,
+    /// the items of the Strs and Buffers that literals and field names were
+    /// lowered to, by content, so each is stored once
+    strs: HashMap<String, usize>,
+    buffers: HashMap<Vec<u8>, usize>,
+    next_var: Var,
+    errors: Vec<ResolveError>,
+}
+
+/// the text of a string literal. the lexer only lets through strings that are
+/// valid utf-8, since the source is, and escapes are checked
+fn str_text(text: Vec<u8>) -> String {
+    String::from_utf8(text).expect("string literals are valid utf-8")
+}
+
+/// the item of a `const` whose value is a literal, which is there as soon as
+/// the module is loaded
+fn literal_item(value: &ast::Expr) -> Option<ModuleItem> {
+    let literal = match &value.kind {
+        ast::ExprKind::Int(v) => LiteralValue::Integer(*v),
+        ast::ExprKind::Real(v) => LiteralValue::Real(*v),
+        ast::ExprKind::Bool(v) => LiteralValue::Integer(*v as i64),
+        ast::ExprKind::None => LiteralValue::None,
+        ast::ExprKind::Str(s) => return Some(ModuleItem::Str(str_text(s.clone()))),
+        ast::ExprKind::Bytes(s) => return Some(ModuleItem::Buffer(s.clone())),
+        ast::ExprKind::Unary {
+            op: UnaryOpType::Neg,
+            expr,
+        } => match &expr.kind {
+            ast::ExprKind::Int(v) => LiteralValue::Integer(v.wrapping_neg()),
+            ast::ExprKind::Real(v) => LiteralValue::Real(-v),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(ModuleItem::LiteralValue(literal))
+}
+
+/// the names that `pattern` binds, in a `let` or a parameter
+fn pattern_names(pattern: &Pattern, names: &mut Vec<Ident>) {
+    match &pattern.kind {
+        PatternKind::Wildcard => {}
+        PatternKind::Place(place) => names.push(place_name(place)),
+        PatternKind::Tuple(items) => {
+            for item in items {
+                pattern_names(item, names);
+            }
+        }
+        PatternKind::List { items, rest } => {
+            for item in items {
+                pattern_names(item, names);
+            }
+            if let Some(place) = rest.as_ref().and_then(|r| r.place.as_ref()) {
+                names.push(place_name(place));
+            }
+        }
+    }
+}
+
+/// adds the names that `statements` assign to, in assignments or in the
+/// functions written among them, to `names`
+fn assigned_names(statements: &[ast::Statement], names: &mut HashSet<String>) {
+    for statement in statements {
+        match &statement.kind {
+            ast::StatementKind::Fn(def) => assigned_names(&def.body.body, names),
+            ast::StatementKind::Let { value, .. } | ast::StatementKind::Var { value, .. } => {
+                if let Some(value) = value {
+                    expr_assigned_names(value, names);
+                }
+            }
+            ast::StatementKind::Return(value) => {
+                if let Some(value) = value {
+                    expr_assigned_names(value, names);
+                }
+            }
+            ast::StatementKind::Const { value, .. } | ast::StatementKind::Expr(value) => {
+                expr_assigned_names(value, names)
+            }
+            ast::StatementKind::IfElse(s) => {
+                for i in std::iter::once(&s.if_).chain(&s.else_if) {
+                    expr_assigned_names(&i.condition, names);
+                    assigned_names(&i.body.body, names);
+                }
+                if let Some(block) = &s.else_ {
+                    assigned_names(&block.body, names);
+                }
+            }
+            ast::StatementKind::Loop(l) => {
+                if let Some(condition) = &l.condition {
+                    expr_assigned_names(condition, names);
+                }
+                assigned_names(&l.body.body, names);
+            }
+            ast::StatementKind::For(f) => {
+                expr_assigned_names(&f.seq, names);
+                assigned_names(&f.body.body, names);
+            }
+            ast::StatementKind::Assign { place, value, .. } => {
+                place_assigned_names(place, names);
+                expr_assigned_names(value, names);
+            }
+            ast::StatementKind::AssignPattern { pattern, value } => {
+                pattern_assigned_names(pattern, names);
+                expr_assigned_names(value, names);
+            }
+            ast::StatementKind::Import(_)
+            | ast::StatementKind::Record(_)
+            | ast::StatementKind::Break { .. }
+            | ast::StatementKind::Continue { .. }
+            | ast::StatementKind::Error => {}
+        }
+    }
+}
+
+fn pattern_assigned_names(pattern: &Pattern, names: &mut HashSet<String>) {
+    match &pattern.kind {
+        PatternKind::Wildcard => {}
+        PatternKind::Place(place) => place_assigned_names(place, names),
+        PatternKind::Tuple(items) => {
+            for item in items {
+                pattern_assigned_names(item, names);
+            }
+        }
+        PatternKind::List { items, rest } => {
+            for item in items {
+                pattern_assigned_names(item, names);
+            }
+            if let Some(place) = rest.as_ref().and_then(|r| r.place.as_ref()) {
+                place_assigned_names(place, names);
+            }
+        }
+    }
+}
+
+fn place_assigned_names(place: &ast::Expr, names: &mut HashSet<String>) {
+    match &place.kind {
+        ast::ExprKind::Name(name) => {
+            names.insert(name.clone());
+        }
+        _ => expr_assigned_names(place, names),
+    }
+}
+
+/// adds the names that the functions written in `expr` assign to
+fn expr_assigned_names(expr: &ast::Expr, names: &mut HashSet<String>) {
+    match &expr.kind {
+        ast::ExprKind::Fn { body, .. } => assigned_names(&body.body, names),
+        ast::ExprKind::Interpolated(parts) => {
+            for part in parts {
+                if let InterpolationPart::Expr(e) = part {
+                    expr_assigned_names(e, names);
+                }
+            }
+        }
+        ast::ExprKind::Tuple(items) | ast::ExprKind::List(items) => {
+            for item in items {
+                expr_assigned_names(item, names);
+            }
+        }
+        ast::ExprKind::Binary { lhs, rhs, .. } => {
+            expr_assigned_names(lhs, names);
+            expr_assigned_names(rhs, names);
+        }
+        ast::ExprKind::Unary { expr, .. } | ast::ExprKind::Field { expr, .. } => {
+            expr_assigned_names(expr, names)
+        }
+        ast::ExprKind::Call { func, args } => {
+            expr_assigned_names(func, names);
+            for arg in args {
+                expr_assigned_names(arg, names);
+            }
+        }
+        ast::ExprKind::Index { seq, index } => {
+            expr_assigned_names(seq, names);
+            expr_assigned_names(index, names);
+        }
+        ast::ExprKind::Slice { seq, start, end } => {
+            expr_assigned_names(seq, names);
+            for bound in start.iter().chain(end) {
+                expr_assigned_names(bound, names);
+            }
+        }
+        ast::ExprKind::Int(_)
+        | ast::ExprKind::Real(_)
+        | ast::ExprKind::Bool(_)
+        | ast::ExprKind::None
+        | ast::ExprKind::Str(_)
+        | ast::ExprKind::Bytes(_)
+        | ast::ExprKind::Name(_)
+        | ast::ExprKind::Error => {}
+    }
+}
+
+/// the name of a place in a pattern that binds names
+fn place_name(place: &ast::Expr) -> Ident {
+    match &place.kind {
+        ast::ExprKind::Name(name) => Ident {
+            name: name.clone(),
+            span: place.span,
+        },
+        _ => unreachable!("the parser only accepts names in patterns that bind"),
+    }
+}
+
+fn source_span(span: Span) -> SourceSpan {
+    SourceSpan {
+        start: span.start,
+        len: span.len,
+    }
+}
+
+fn var_expr(var: Var, span: SourceSpan) -> stage0::Expr {
+    stage0::Span::new(span, stage0::ExprKind::Var(var))
+}
+
+fn literal(value: LiteralValue, span: SourceSpan) -> stage0::Expr {
+    stage0::Span::new(span, stage0::ExprKind::LiteralValue(value))
+}
+
+/// loads module item `item`
+fn item_load(item: usize, span: SourceSpan) -> stage0::Expr {
+    let kind = stage0::ExprKind::SeqIndex {
+        seq: Box::new(stage0::Span::new(span, stage0::ExprKind::ModuleRef)),
+        index: Box::new(literal(LiteralValue::Integer(item as i64), span)),
+    };
+    stage0::Span::new(span, kind)
+}
+
+impl Resolver {
+    fn error(&mut self, error_kind: ResolveErrorKind, span: Span) {
+        self.errors.push(ResolveError { error_kind, span });
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes.iter().rev().find_map(|s| s.get(name).copied())
+    }
+
+    fn depth(&self) -> usize {
+        self.fns.len() - 1
+    }
+
+    /// a new variable, which no name is bound to
+    fn new_var(&mut self) -> Var {
+        let var = self.next_var;
+        self.next_var += 1;
+        var
+    }
+
+    /// binds `name` to a new variable in the innermost scope
+    fn bind(&mut self, name: &Ident) -> Var {
+        let var = self.new_var();
+        let binding = Binding::Var {
+            var,
+            depth: self.depth(),
+        };
+        let scope = self.scopes.last_mut().unwrap();
+        scope.insert(name.name.clone(), binding);
+        var
+    }
+
+    /// sets the item of a `const` or `var` to `value`, when the main function
+    /// gets to its statement
+    fn init_item(
+        &mut self,
+        item: usize,
+        name: &Ident,
+        value: &ast::Expr,
+        span: Span,
+        body: &mut Vec<stage0::Statement>,
+    ) {
+        let value = self.lower_expr(value);
+        let kind = stage0::StatementKind::Assign {
+            place: Box::new(item_load(item, source_span(name.span))),
+            value: Box::new(value),
+        };
+        body.push(stage0::Span::new(source_span(span), kind));
+    }
+
+    /// the variable `var`, bound `depth` functions deep, is used in the
+    /// current function. if that's a different function, every function from
+    /// there to here captures it
+    fn use_var(&mut self, var: Var, depth: usize, span: Span) {
+        if depth == self.depth() {
+            return;
+        }
+        if self.fns[depth + 1..].iter().any(|f| !f.closure) {
+            self.error(ResolveErrorKind::Captured, span);
+            return;
+        }
+        if !self.fns[depth].cells.contains(&var) {
+            self.fns[depth].cells.push(var);
+        }
+        for f in &mut self.fns[depth + 1..] {
+            if !f.captures.contains(&var) {
+                f.captures.push(var);
+            }
+        }
+    }
+
+    /// reports the names that `patterns` bind more than once
+    fn check_duplicates(&mut self, patterns: &[Pattern]) {
+        let mut names = Vec::new();
+        for pattern in patterns {
+            pattern_names(pattern, &mut names);
+        }
+        let mut seen = HashMap::new();
+        for name in names {
+            match seen.get(&name.name) {
+                Some(&first) => self.error(ResolveErrorKind::Duplicate { first }, name.span),
+                None => {
+                    seen.insert(name.name.clone(), name.span);
+                }
+            }
+        }
+    }
+
+    /// starts lowering a function, with `params` bound in a new scope, and
+    /// `body` its statements.
+    /// returns the arguments, and the statements that take apart the ones
+    /// that are patterns, which go before the body
+    fn enter_fn(
+        &mut self,
+        params: &[Pattern],
+        body: &[ast::Statement],
+        closure: bool,
+    ) -> (Vec<stage0::Span<Var>>, Vec<stage0::Statement>) {
+        let mut assigned = HashSet::new();
+        assigned_names(body, &mut assigned);
+        self.fns.push(FnScope {
+            closure,
+            loops: Vec::new(),
+            next_label: 0,
+            captures: Vec::new(),
+            cells: Vec::new(),
+            assigned,
+        });
+        self.scopes.push(HashMap::new());
+        self.check_duplicates(params);
+        let mut args = Vec::new();
+        let mut prologue = Vec::new();
+        for param in params {
+            let span = source_span(param.span);
+            let var = match &param.kind {
+                PatternKind::Place(place) => self.bind(&place_name(place)),
+                PatternKind::Wildcard => self.new_var(),
+                _ => {
+                    let var = self.new_var();
+                    let value = stage0::Span::new(span, stage0::ExprKind::Var(var));
+                    self.destructure(param, value, true, &mut prologue);
+                    var
+                }
+            };
+            args.push(stage0::Span::new(span, var));
+        }
+        (args, prologue)
+    }
+
+    fn exit_fn(
+        &mut self,
+        args: Vec<stage0::Span<Var>>,
+        body: Vec<stage0::Statement>,
+    ) -> stage0::Function {
+        self.scopes.pop();
+        let f = self.fns.pop().unwrap();
+        stage0::Function {
+            args,
+            body,
+            captures: f.captures,
+            cells: f.cells,
+        }
+    }
+
+    /// lowers a function that's written inside another, and returns the
+    /// closure of it
+    fn lower_closure(
+        &mut self,
+        params: &[Pattern],
+        block: &Block,
+        span: SourceSpan,
+    ) -> stage0::Expr {
+        let (args, mut body) = self.enter_fn(params, &block.body, true);
+        body.extend(self.lower_statements(&block.body));
+        let f = self.exit_fn(args, body);
+        let captures: Vec<_> = f
+            .captures
+            .iter()
+            .map(|v| stage0::Span::new(span, *v))
+            .collect();
+        let item = self.items.len();
+        self.items.push(Some(ModuleItem::Function(f)));
+        let func = item_load(item, span);
+        if captures.is_empty() {
+            return func;
+        }
+        let kind = stage0::ExprKind::Closure {
+            func: Box::new(func),
+            captures,
+        };
+        stage0::Span::new(span, kind)
+    }
+
+    fn lower_block(&mut self, block: &Block) -> Vec<stage0::Statement> {
+        self.scopes.push(HashMap::new());
+        let body = self.lower_statements(&block.body);
+        self.scopes.pop();
+        body
+    }
+
+    /// lowers the statements of a block into the innermost scope
+    fn lower_statements(&mut self, statements: &[ast::Statement]) -> Vec<stage0::Statement> {
+        let mut body = Vec::new();
+        for statement in statements {
+            self.lower_statement(statement, &mut body);
+        }
+        body
+    }
+
+    /// lowers `statement` onto the end of `body`
+    fn lower_statement(&mut self, statement: &ast::Statement, body: &mut Vec<stage0::Statement>) {
+        let span = source_span(statement.span);
+        let kind = match &statement.kind {
+            ast::StatementKind::Fn(def) => {
+                // the name is bound first, so that the function can call itself
+                let var = self.bind(&def.name);
+                let name_span = source_span(def.name.span);
+                body.push(stage0::Span::new(
+                    name_span,
+                    stage0::StatementKind::BindVar(var),
+                ));
+                let value = self.lower_closure(&def.params, &def.body, span);
+                stage0::StatementKind::Assign {
+                    place: Box::new(stage0::Span::new(name_span, stage0::ExprKind::Var(var))),
+                    value: Box::new(value),
+                }
+            }
+            ast::StatementKind::Let { pattern, value } => {
+                let typ = match value {
+                    Some(value) => self.type_of(value),
+                    None => Type::Unknown,
+                };
+                let value = match value {
+                    Some(value) => self.lower_expr(value),
+                    None => literal(LiteralValue::None, span),
+                };
+                self.check_duplicates(std::slice::from_ref(pattern));
+                self.destructure(pattern, value, true, body);
+                if let PatternKind::Place(place) = &pattern.kind {
+                    self.set_type(&place_name(place), typ);
+                }
+                return;
+            }
+            ast::StatementKind::Const { .. }
+            | ast::StatementKind::Var { .. }
+            | ast::StatementKind::Import(_)
+            | ast::StatementKind::Record(_) => {
+                self.error(ResolveErrorKind::NotTopLevel, statement.span);
+                return;
+            }
+            ast::StatementKind::IfElse(s) => {
+                let if_ = self.lower_if(&s.if_);
+                let else_if = s.else_if.iter().map(|i| self.lower_if(i)).collect();
+                let else_ = match &s.else_ {
+                    Some(block) => self.lower_block(block),
+                    None => Vec::new(),
+                };
+                stage0::StatementKind::IfElse(stage0::IfElse {
+                    if_,
+                    else_if,
+                    else_,
+                })
+            }
+            ast::StatementKind::Loop(l) => {
+                let condition = l.condition.as_ref().map(|c| self.lower_expr(c));
+                let id = self.enter_loop(&l.label);
+                let body = self.lower_block(&l.body);
+                self.fns.last_mut().unwrap().loops.pop();
+                stage0::StatementKind::Loop(stage0::Loop {
+                    condition,
+                    label: Some(id),
+                    body,
+                })
+            }
+            ast::StatementKind::For(f) => self.lower_for(f, span, body),
+            ast::StatementKind::Break { label } => match self.loop_id(label, statement.span) {
+                Some(id) => stage0::StatementKind::Break { label: Some(id) },
+                None => return,
+            },
+            ast::StatementKind::Continue { label } => match self.loop_id(label, statement.span) {
+                Some(id) => stage0::StatementKind::Continue { label: Some(id) },
+                None => return,
+            },
+            ast::StatementKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.lower_expr(value),
+                    None => literal(LiteralValue::None, span),
+                };
+                stage0::StatementKind::Return(value)
+            }
+            ast::StatementKind::Assign {
+                place,
+                op: Some(op),
+                value,
+            } => {
+                self.lower_compound_assign(place, *op, value, span, body);
+                return;
+            }
+            ast::StatementKind::Assign {
+                place,
+                op: None,
+                value,
+            } => stage0::StatementKind::Assign {
+                place: Box::new(self.lower_place(place)),
+                value: Box::new(self.lower_expr(value)),
+            },
+            ast::StatementKind::AssignPattern { pattern, value } => {
+                let value = self.lower_expr(value);
+                self.destructure(pattern, value, false, body);
+                return;
+            }
+            ast::StatementKind::Expr(expr) => stage0::StatementKind::Expr(self.lower_expr(expr)),
+            ast::StatementKind::Error => return,
+        };
+        body.push(stage0::Span::new(span, kind));
+    }
+
+    /// lowers `place op= value`. the seq and index of an indexed place are
+    /// computed once, into temporaries, and the place is read and written
+    /// through them
+    fn lower_compound_assign(
+        &mut self,
+        place: &ast::Expr,
+        op: BinaryOpType,
+        value: &ast::Expr,
+        span: SourceSpan,
+        body: &mut Vec<stage0::Statement>,
+    ) {
+        let place_span = source_span(place.span);
+        let (lowered, read) = match &place.kind {
+            ast::ExprKind::Index { seq, index } => {
+                let seq = self.lower_to_temp(seq, body);
+                let index = self.lower_to_temp(index, body);
+                let element = || {
+                    let kind = stage0::ExprKind::SeqIndex {
+                        seq: Box::new(var_expr(seq, place_span)),
+                        index: Box::new(var_expr(index, place_span)),
+                    };
+                    stage0::Span::new(place_span, kind)
+                };
+                (element(), element())
+            }
+            ast::ExprKind::Field { expr, name } if self.module_of(expr).is_none() => {
+                let typ = self.type_of(expr);
+                let record_span = source_span(expr.span);
+                let record = self.lower_to_temp(expr, body);
+                let field = |r: &mut Self| {
+                    r.lower_record_field(var_expr(record, record_span), &typ, name, place_span)
+                };
+                let lowered = field(self);
+                let errors = self.errors.len();
+                let read = field(self);
+                self.errors.truncate(errors);
+                (lowered, read)
+            }
+            _ => (self.lower_place(place), self.lower_expr_again(place)),
+        };
+        let value = stage0::ExprKind::BinaryOp(BinaryOp {
+            op_type: op,
+            lhs: Box::new(read),
+            rhs: Box::new(self.lower_expr(value)),
+        });
+        let kind = stage0::StatementKind::Assign {
+            place: Box::new(lowered),
+            value: Box::new(stage0::Span::new(span, value)),
+        };
+        body.push(stage0::Span::new(span, kind));
+    }
+
+    /// computes `expr` into a new variable, which no name is bound to
+    fn lower_to_temp(&mut self, expr: &ast::Expr, body: &mut Vec<stage0::Statement>) -> Var {
+        let span = source_span(expr.span);
+        let value = self.lower_expr(expr);
+        let var = self.new_var();
+        body.push(stage0::Span::new(span, stage0::StatementKind::BindVar(var)));
+        let kind = stage0::StatementKind::Assign {
+            place: Box::new(var_expr(var, span)),
+            value: Box::new(value),
+        };
+        body.push(stage0::Span::new(span, kind));
+        var
+    }
+
+    /// takes `value` apart into `pattern`, binding its names if `bind`, and
+    /// assigning to its places otherwise
+    fn destructure(
+        &mut self,
+        pattern: &Pattern,
+        value: stage0::Expr,
+        bind: bool,
+        body: &mut Vec<stage0::Statement>,
+    ) {
+        match &pattern.kind {
+            PatternKind::Wildcard => {
+                let span = source_span(pattern.span);
+                body.push(stage0::Span::new(span, stage0::StatementKind::Expr(value)));
+            }
+            PatternKind::Place(place) => self.assign_place(place, value, bind, body),
+            PatternKind::Tuple(items) => {
+                self.destructure_seq(pattern, items, None, value, bind, body)
+            }
+            PatternKind::List { items, rest } => {
+                self.destructure_seq(pattern, items, rest.as_ref(), value, bind, body)
+            }
+        }
+    }
+
+    /// assigns `value` to `place`, after binding it if `bind`
+    fn assign_place(
+        &mut self,
+        place: &ast::Expr,
+        value: stage0::Expr,
+        bind: bool,
+        body: &mut Vec<stage0::Statement>,
+    ) {
+        let span = source_span(place.span);
+        let place = if bind {
+            let var = self.bind(&place_name(place));
+            body.push(stage0::Span::new(span, stage0::StatementKind::BindVar(var)));
+            stage0::Span::new(span, stage0::ExprKind::Var(var))
+        } else {
+            self.lower_place(place)
+        };
+        let kind = stage0::StatementKind::Assign {
+            place: Box::new(place),
+            value: Box::new(value),
+        };
+        body.push(stage0::Span::new(span, kind));
+    }
+
+    /// takes apart a tuple or list pattern, after checking that `value` has
+    /// the right number of items
+    fn destructure_seq(
+        &mut self,
+        pattern: &Pattern,
+        items: &[Pattern],
+        rest: Option<&ast::Rest>,
+        value: stage0::Expr,
+        bind: bool,
+        body: &mut Vec<stage0::Statement>,
+    ) {
+        let span = source_span(pattern.span);
+        let check = stage0::ExprKind::SeqCheckLen {
+            seq: Box::new(value),
+            len: items.len() as u32,
+            at_least: rest.is_some(),
+        };
+        let seq = self.new_var();
+        body.push(stage0::Span::new(span, stage0::StatementKind::BindVar(seq)));
+        body.push(stage0::Span::new(
+            span,
+            stage0::StatementKind::Assign {
+                place: Box::new(stage0::Span::new(span, stage0::ExprKind::Var(seq))),
+                value: Box::new(stage0::Span::new(span, check)),
+            },
+        ));
+        let seq = |span| Box::new(stage0::Span::new(span, stage0::ExprKind::Var(seq)));
+        // the items after the rest are counted from the end
+        let from_end = |len: usize, span| {
+            let seq_len = stage0::Span::new(span, stage0::ExprKind::SeqLen { seq: seq(span) });
+            if len == 0 {
+                return seq_len;
+            }
+            let kind = stage0::ExprKind::BinaryOp(BinaryOp {
+                op_type: BinaryOpType::Sub,
+                lhs: Box::new(seq_len),
+                rhs: Box::new(literal(LiteralValue::Integer(len as i64), span)),
+            });
+            stage0::Span::new(span, kind)
+        };
+        let rest_index = rest.map_or(items.len(), |r| r.index);
+        for (i, item) in items.iter().enumerate() {
+            if item.kind == PatternKind::Wildcard {
+                continue;
+            }
+            let item_span = source_span(item.span);
+            let index = if i < rest_index {
+                literal(LiteralValue::Integer(i as i64), item_span)
+            } else {
+                from_end(items.len() - i, item_span)
+            };
+            let kind = stage0::ExprKind::SeqIndex {
+                seq: seq(item_span),
+                index: Box::new(index),
+            };
+            self.destructure(item, stage0::Span::new(item_span, kind), bind, body);
+        }
+        if let Some(ast::Rest {
+            index,
+            place: Some(place),
+            span,
+        }) = rest
+        {
+            let span = source_span(*span);
+            let kind = stage0::ExprKind::ListGetSlice {
+                list: seq(span),
+                a: Box::new(literal(LiteralValue::Integer(*index as i64), span)),
+                b: Box::new(from_end(items.len() - index, span)),
+            };
+            self.assign_place(place, stage0::Span::new(span, kind), bind, body);
+        }
+    }
+
+    /// starts lowering a loop with `label`, and returns its label id
+    fn enter_loop(&mut self, label: &Option<Ident>) -> usize {
+        let f = self.fns.last_mut().unwrap();
+        let id = f.next_label;
+        f.next_label += 1;
+        f.loops.push((label.as_ref().map(|l| l.name.clone()), id));
+        id
+    }
+
+    /// lowers a `for` loop to a Loop over the loop's state; `body` gets the
+    /// statements that create the state, which go before the Loop
+    fn lower_for(
+        &mut self,
+        f: &ast::For,
+        span: SourceSpan,
+        body: &mut Vec<stage0::Statement>,
+    ) -> stage0::StatementKind {
+        let seq_span = source_span(f.seq.span);
+        let seq = self.lower_expr(&f.seq);
+        let state = self.new_var();
+        let state_expr = |span| Box::new(stage0::Span::new(span, stage0::ExprKind::Var(state)));
+        body.push(stage0::Span::new(
+            span,
+            stage0::StatementKind::BindVar(state),
+        ));
+        let init = stage0::ExprKind::TupleCreate(vec![
+            seq,
+            literal(LiteralValue::None, seq_span),
+            literal(LiteralValue::None, seq_span),
+        ]);
+        body.push(stage0::Span::new(
+            seq_span,
+            stage0::StatementKind::Assign {
+                place: state_expr(seq_span),
+                value: Box::new(stage0::Span::new(seq_span, init)),
+            },
+        ));
+        let id = self.enter_loop(&f.label);
+        self.scopes.push(HashMap::new());
+        let pattern_span = source_span(f.pattern.span);
+        // the state's item 2 is the current item; see the vm's IterNext
+        let item = stage0::ExprKind::SeqIndex {
+            seq: state_expr(pattern_span),
+            index: Box::new(literal(LiteralValue::Integer(2), pattern_span)),
+        };
+        let mut loop_body = Vec::new();
+        self.check_duplicates(std::slice::from_ref(&f.pattern));
+        self.destructure(
+            &f.pattern,
+            stage0::Span::new(pattern_span, item),
+            true,
+            &mut loop_body,
+        );
+        loop_body.extend(self.lower_statements(&f.body.body));
+        self.scopes.pop();
+        self.fns.last_mut().unwrap().loops.pop();
+        let condition = stage0::ExprKind::IterNext(state_expr(seq_span));
+        stage0::StatementKind::Loop(stage0::Loop {
+            condition: Some(stage0::Span::new(seq_span, condition)),
+            label: Some(id),
+            body: loop_body,
+        })
+    }
+
+    fn lower_if(&mut self, if_: &ast::If) -> stage0::If {
+        stage0::If {
+            condition: self.lower_expr(&if_.condition),
+            body: self.lower_block(&if_.body),
+        }
+    }
+
+    /// the label id of the loop that a `break` or `continue` with `label`
+    /// refers to
+    fn loop_id(&mut self, label: &Option<Ident>, span: Span) -> Option<usize> {
+        let loops = &self.fns.last().unwrap().loops;
+        if loops.is_empty() {
+            self.error(ResolveErrorKind::OutsideLoop, span);
+            return None;
+        }
+        let label = match label {
+            Some(label) => label,
+            None => return loops.last().map(|(_, id)| *id),
+        };
+        let found = loops
+            .iter()
+            .rev()
+            .find(|(name, _)| name.as_ref() == Some(&label.name))
+            .map(|(_, id)| *id);
+        if found.is_none() {
+            self.error(ResolveErrorKind::UndefinedLabel, label.span);
+        }
+        found
+    }
+
+    /// lowers the left side of an assignment
+    fn lower_place(&mut self, place: &ast::Expr) -> stage0::Expr {
+        let span = source_span(place.span);
+        let name = match &place.kind {
+            ast::ExprKind::Name(name) => name,
+            ast::ExprKind::Index { .. } => return self.lower_expr(place),
+            ast::ExprKind::Field { .. } => return self.lower_field(place, true),
+            _ => unreachable!("the parser only accepts names and indexes as places"),
+        };
+        match self.lookup(name) {
+            Some(Binding::Var { var, depth }) => {
+                self.use_var(var, depth, place.span);
+                return stage0::Span::new(span, stage0::ExprKind::Var(var));
+            }
+            Some(Binding::Item {
+                item,
+                assignable: true,
+            }) => return item_load(item, span),
+            Some(Binding::Item { .. } | Binding::Module { .. } | Binding::Record { .. }) => {
+                self.error(ResolveErrorKind::NotAssignable, place.span)
+            }
+            None => self.error(ResolveErrorKind::Undefined, place.span),
+        }
+        literal(LiteralValue::None, span)
+    }
+
+    /// lowers an expression that was already lowered once, without reporting
+    /// its errors a second time
+    fn lower_expr_again(&mut self, expr: &ast::Expr) -> stage0::Expr {
+        let errors = self.errors.len();
+        let lowered = self.lower_expr(expr);
+        self.errors.truncate(errors);
+        lowered
+    }
+
+    fn lower_exprs(&mut self, exprs: &[ast::Expr]) -> Vec<stage0::Expr> {
+        exprs.iter().map(|e| self.lower_expr(e)).collect()
+    }
+
+    fn lower_expr(&mut self, expr: &ast::Expr) -> stage0::Expr {
+        let span = source_span(expr.span);
+        let kind = match &expr.kind {
+            ast::ExprKind::Int(v) => stage0::ExprKind::LiteralValue(LiteralValue::Integer(*v)),
+            ast::ExprKind::Real(v) => stage0::ExprKind::LiteralValue(LiteralValue::Real(*v)),
+            ast::ExprKind::Bool(v) => {
+                stage0::ExprKind::LiteralValue(LiteralValue::Integer(*v as i64))
+            }
+            ast::ExprKind::None => stage0::ExprKind::LiteralValue(LiteralValue::None),
+            ast::ExprKind::Str(s) => return self.str_literal(s.clone(), span),
+            ast::ExprKind::Bytes(s) => return self.buffer_literal(s.clone(), span),
+            ast::ExprKind::Interpolated(parts) => return self.lower_interpolated(parts, span),
+            ast::ExprKind::Name(name) => return self.lower_name(name, expr.span),
+            ast::ExprKind::Tuple(items) | ast::ExprKind::List(items) if items.len() > 255 => {
+                let what = "tuples and lists of more than 255 items";
+                self.error(ResolveErrorKind::Unsupported { what }, expr.span);
+                stage0::ExprKind::LiteralValue(LiteralValue::None)
+            }
+            ast::ExprKind::Tuple(items) => stage0::ExprKind::TupleCreate(self.lower_exprs(items)),
+            ast::ExprKind::List(items) => stage0::ExprKind::ListCreate(self.lower_exprs(items)),
+            ast::ExprKind::Binary { op, lhs, rhs } => stage0::ExprKind::BinaryOp(BinaryOp {
+                op_type: *op,
+                lhs: Box::new(self.lower_expr(lhs)),
+                rhs: Box::new(self.lower_expr(rhs)),
+            }),
+            ast::ExprKind::Unary { op, expr } => stage0::ExprKind::UnaryOp(UnaryOp {
+                op_type: *op,
+                expr: Box::new(self.lower_expr(expr)),
+            }),
+            ast::ExprKind::Call { func, args } => {
+                if let Some(kind) = self.lower_builtin(func, args, expr.span) {
+                    kind
+                } else if let Some(kind) = self.lower_record(func, args, expr.span) {
+                    kind
+                } else {
+                    stage0::ExprKind::Call {
+                        func: Box::new(self.lower_expr(func)),
+                        args: self.lower_exprs(args),
+                    }
+                }
+            }
+            ast::ExprKind::Fn { params, body } => return self.lower_closure(params, body, span),
+            ast::ExprKind::Index { seq, index } => stage0::ExprKind::SeqIndex {
+                seq: Box::new(self.lower_expr(seq)),
+                index: Box::new(self.lower_expr(index)),
+            },
+            ast::ExprKind::Field { .. } => return self.lower_field(expr, false),
+            ast::ExprKind::Slice { seq, start, end } => {
+                let value = self.lower_expr(seq);
+                let a = match start {
+                    Some(start) => self.lower_expr(start),
+                    None => literal(LiteralValue::Integer(0), span),
+                };
+                let b = match end {
+                    Some(end) => self.lower_expr(end),
+                    None => literal(LiteralValue::None, span),
+                };
+                stage0::ExprKind::SeqGetSlice {
+                    seq: Box::new(value),
+                    a: Box::new(a),
+                    b: Box::new(b),
+                }
+            }
+            // the parser already reported it
+            ast::ExprKind::Error => stage0::ExprKind::LiteralValue(LiteralValue::None),
+        };
+        stage0::Span::new(span, kind)
+    }
+
+    fn lower_name(&mut self, name: &str, span: Span) -> stage0::Expr {
+        let source = source_span(span);
+        match self.lookup(name) {
+            Some(Binding::Var { var, depth }) => {
+                self.use_var(var, depth, span);
+                return stage0::Span::new(source, stage0::ExprKind::Var(var));
+            }
+            Some(
+                Binding::Item { item, .. }
+                | Binding::Module { item, .. }
+                | Binding::Record { item, .. },
+            ) => {
+                return item_load(item, source);
+            }
+            None => self.error(ResolveErrorKind::Undefined, span),
+        }
+        literal(LiteralValue::None, source)
+    }
+
+    /// lowers `expr`, which is a field, to be read, or assigned to if
+    /// `assign`
+    fn lower_field(&mut self, expr: &ast::Expr, assign: bool) -> stage0::Expr {
+        let span = source_span(expr.span);
+        let (inner, name) = match &expr.kind {
+            ast::ExprKind::Field { expr, name } => (expr, name),
+            _ => unreachable!("lower_field is only called on fields"),
+        };
+        let (item, exports) = match self.module_of(inner) {
+            Some(module) => module,
+            None => {
+                let typ = self.type_of(inner);
+                let record = self.lower_expr(inner);
+                return self.lower_record_field(record, &typ, name, span);
+            }
+        };
+        match self.modules[exports].get(&name.name) {
+            Some(export) if assign && !export.assignable => {
+                self.error(ResolveErrorKind::NotAssignable, expr.span)
+            }
+            Some(export) => {
+                let kind = stage0::ExprKind::SeqIndex {
+                    seq: Box::new(item_load(item, source_span(inner.span))),
+                    index: Box::new(literal(LiteralValue::Integer(export.item as i64), span)),
+                };
+                return stage0::Span::new(span, kind);
+            }
+            None => self.error(ResolveErrorKind::UndefinedMember, name.span),
+        }
+        literal(LiteralValue::None, span)
+    }
+
+    /// the item and exports of the module that `expr` names, if it names one
+    fn module_of(&self, expr: &ast::Expr) -> Option<(usize, usize)> {
+        match &expr.kind {
+            ast::ExprKind::Name(n) => match self.lookup(n) {
+                Some(Binding::Module { item, exports }) => Some((item, exports)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// lowers `record.name`, a field of a value that isn't a module, where
+    /// `record` is already lowered and `typ` is its type. it's a SeqGet when
+    /// the type of the record is known, and a RecordGet that finds the field
+    /// by name when it isn't
+    fn lower_record_field(
+        &mut self,
+        record: stage0::Expr,
+        typ: &Type,
+        name: &Ident,
+        span: SourceSpan,
+    ) -> stage0::Expr {
+        let record = Box::new(record);
+        let name_span = source_span(name.span);
+        let kind = match typ {
+            Type::Record(typ) => match typ.field_index(&name.name) {
+                Some(index) => stage0::ExprKind::SeqIndex {
+                    seq: record,
+                    index: Box::new(literal(LiteralValue::Integer(index as i64), name_span)),
+                },
+                None => {
+                    self.error(ResolveErrorKind::UndefinedField, name.span);
+                    return literal(LiteralValue::None, span);
+                }
+            },
+            _ => {
+                let item = self.str_item(name.name.clone());
+                stage0::ExprKind::RecordGet {
+                    record,
+                    field: Box::new(item_load(item, name_span)),
+                }
+            }
+        };
+        stage0::Span::new(span, kind)
+    }
+
+    /// lowers a call that creates a record, or returns None if `func` isn't
+    /// a record's name
+    fn lower_record(
+        &mut self,
+        func: &ast::Expr,
+        args: &[ast::Expr],
+        span: Span,
+    ) -> Option<stage0::ExprKind> {
+        let (item, record) = match &func.kind {
+            ast::ExprKind::Name(name) => match self.lookup(name) {
+                Some(Binding::Record { item, record }) => (item, record),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let expected = self.records[record].fields.len();
+        if args.len() != expected {
+            self.error(ResolveErrorKind::RecordArgs { expected }, span);
+            return Some(stage0::ExprKind::LiteralValue(LiteralValue::None));
+        }
+        let mut items = vec![item_load(item, source_span(func.span))];
+        items.extend(self.lower_exprs(args));
+        Some(stage0::ExprKind::TupleCreate(items))
+    }
+
+    /// the item of a `record`, whose type is added to `records`. fields with
+    /// the same name are reported
+    fn record_item(&mut self, record: &ast::Record) -> ModuleItem {
+        let mut seen = HashMap::new();
+        for field in &record.fields {
+            match seen.get(&field.name) {
+                Some(&first) => self.error(ResolveErrorKind::Duplicate { first }, field.span),
+                None => {
+                    seen.insert(&field.name, field.span);
+                }
+            }
+        }
+        let name = record.name.name.clone();
+        let fields: Vec<_> = record.fields.iter().map(|f| f.name.clone()).collect();
+        if fields.len() > 254 {
+            let what = "records with more than 254 fields";
+            self.error(ResolveErrorKind::Unsupported { what }, record.name.span);
+        }
+        self.records.push(Rc::new(RecordType {
+            name: name.clone(),
+            fields: fields.clone(),
+        }));
+        ModuleItem::RecordType { name, fields }
+    }
+
+    /// the type of `expr`, as far as the resolver knows it: a record that's
+    /// being created, or a variable whose type is known
+    fn type_of(&self, expr: &ast::Expr) -> Type {
+        let name = match &expr.kind {
+            ast::ExprKind::Name(name) => name,
+            ast::ExprKind::Call { func, .. } => match &func.kind {
+                ast::ExprKind::Name(name) => match self.lookup(name) {
+                    Some(Binding::Record { record, .. }) => {
+                        return Type::Record(self.records[record].clone())
+                    }
+                    _ => return Type::Unknown,
+                },
+                _ => return Type::Unknown,
+            },
+            _ => return Type::Unknown,
+        };
+        match self.lookup(name) {
+            Some(Binding::Var { var, .. }) => {
+                self.var_types.get(&var).cloned().unwrap_or(Type::Unknown)
+            }
+            _ => Type::Unknown,
+        }
+    }
+
+    /// sets the type of the variable that `name` was just bound to, unless
+    /// the function assigns to the name somewhere, which could change it
+    fn set_type(&mut self, name: &Ident, typ: Type) {
+        if typ == Type::Unknown || self.fns.last().unwrap().assigned.contains(&name.name) {
+            return;
+        }
+        if let Some(Binding::Var { var, .. }) = self.lookup(&name.name) {
+            self.var_types.insert(var, typ);
+        }
+    }
+
+    /// lowers a call to a builtin, or returns None if `func` isn't one
+    fn lower_builtin(
+        &mut self,
+        func: &ast::Expr,
+        args: &[ast::Expr],
+        span: Span,
+    ) -> Option<stage0::ExprKind> {
+        let name = match &func.kind {
+            ast::ExprKind::Name(name) if self.lookup(name).is_none() => name,
+            _ => return None,
+        };
+        let (_, builtin) = BUILTINS.iter().find(|(n, _)| n == name)?;
+        let expected = builtin.args();
+        if args.len() != expected {
+            self.error(ResolveErrorKind::BuiltinArgs { expected }, span);
+            return Some(stage0::ExprKind::LiteralValue(LiteralValue::None));
+        }
+        let mut args = args.iter().map(|arg| Box::new(self.lower_expr(arg)));
+        let arg = args.next().unwrap();
+        Some(match *builtin {
+            Builtin::Unary(op_type) => stage0::ExprKind::UnaryOp(UnaryOp { op_type, expr: arg }),
+            Builtin::Len => stage0::ExprKind::SeqLen { seq: arg },
+            Builtin::Table => stage0::ExprKind::TableCreate(arg),
+            Builtin::Str => stage0::ExprKind::StrFromBuffer(arg),
+            Builtin::Bytes => stage0::ExprKind::StrToBuffer(arg),
+            Builtin::Find => stage0::ExprKind::StrFind {
+                s: arg,
+                needle: args.next().unwrap(),
+            },
+            Builtin::Map => stage0::ExprKind::MapCreate(arg),
+            Builtin::Remove => stage0::ExprKind::MapRemove {
+                map: arg,
+                key: args.next().unwrap(),
+            },
+            Builtin::Contains => stage0::ExprKind::MapContains {
+                map: arg,
+                key: args.next().unwrap(),
+            },
+            Builtin::Keys => stage0::ExprKind::MapKeys(arg),
+        })
+    }
+
+    /// a byte string literal, which is stored as a Buffer in a module item,
+    /// shared by every literal with the same bytes
+    fn buffer_literal(&mut self, bytes: Vec<u8>, span: SourceSpan) -> stage0::Expr {
+        let items = &mut self.items;
+        let item = *self.buffers.entry(bytes.clone()).or_insert_with(|| {
+            items.push(Some(ModuleItem::Buffer(bytes)));
+            items.len() - 1
+        });
+        item_load(item, span)
+    }
+
+    /// the parts of an interpolated string, joined with Add. each expression
+    /// goes through StrFromValue, so it can be any value
+    fn lower_interpolated(
+        &mut self,
+        parts: &[InterpolationPart],
+        span: SourceSpan,
+    ) -> stage0::Expr {
+        let mut joined: Option<stage0::Expr> = None;
+        for part in parts {
+            let part = match part {
+                InterpolationPart::Text(t, span) => self.str_literal(t.clone(), source_span(*span)),
+                InterpolationPart::Expr(e) => {
+                    let value = Box::new(self.lower_expr(e));
+                    stage0::Span::new(source_span(e.span), stage0::ExprKind::StrFromValue(value))
+                }
+            };
+            joined = Some(match joined {
+                None => part,
+                Some(lhs) => {
+                    let start = lhs.span.start;
+                    let end = part.span.start + part.span.len;
+                    let kind = stage0::ExprKind::BinaryOp(BinaryOp {
+                        op_type: BinaryOpType::Add,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(part),
+                    });
+                    let len = end - start;
+                    stage0::Span::new(SourceSpan { start, len }, kind)
+                }
+            });
+        }
+        joined.unwrap_or_else(|| self.str_literal(Vec::new(), span))
+    }
+
+    /// a string literal, which is stored as a Str in a module item
+    fn str_literal(&mut self, text: Vec<u8>, span: SourceSpan) -> stage0::Expr {
+        let item = self.str_item(str_text(text));
+        item_load(item, span)
+    }
+
+    /// the item of the Str `text`, shared by every literal and field name
+    /// with the same text
+    fn str_item(&mut self, text: String) -> usize {
+        let items = &mut self.items;
+        *self.strs.entry(text.clone()).or_insert_with(|| {
+            items.push(Some(ModuleItem::Str(text)));
+            items.len() - 1
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use peanut_script_vm::bytecode;
+    use peanut_script_vm::datamodel::{Function, Tuple};
+    use peanut_script_vm::VirtualMachine;
+
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn resolve_src(src: &str) -> Result<stage0::Module, Vec<ResolveError>> {
+        let ts = lexer::parse(src.as_bytes()).expect("the source should lex");
+        let module = parser::parse(src.as_bytes(), &ts).expect("the source should parse");
+        resolve(&module, &HashMap::new()).map(|(module, _)| module)
+    }
+
+    /// the errors of a source that doesn't resolve, with their spans
+    fn errors(src: &str) -> Vec<(ResolveErrorKind, Span)> {
+        match resolve_src(src) {
+            Ok(_) => panic!("the source shouldn't resolve"),
+            Err(errors) => errors.into_iter().map(|e| (e.error_kind, e.span)).collect(),
+        }
+    }
+
+    /// the value that the main function of `src` returns, in debug output
+    fn run(src: &str) -> String {
+        let module = resolve_src(src).expect("the source should resolve");
+        let items = module.items.into_iter().map(|i| i.compile()).collect();
+        let program = bytecode::Program {
+            modules: vec![bytecode::Module { items }],
+        };
+        let module: Option<Tuple> = program.into_tuple().get(0).and_then(|m| m.try_into().ok());
+        let main: Option<Function> = module.and_then(|m| m.get(0)?.try_into().ok());
+        let mut vm = VirtualMachine::new(main.expect("item 0 should be the main function"));
+        match vm.run_until_exited() {
+            Ok(value) => format!("{:?}", value),
+            Err(_) => panic!("the program failed at {:?}", vm.backtrace()),
+        }
+    }
+
+    #[test]
+    fn name_and_loop_errors() {
+        let at = |start, len| Span { start, len };
+        let cases = [
+            (
+                "let a = 1\nreturn b + a",
+                ResolveErrorKind::Undefined,
+                at(17, 1),
+            ),
+            (
+                "fn f(a, a) { }",
+                ResolveErrorKind::Duplicate { first: at(5, 1) },
+                at(8, 1),
+            ),
+            (
+                "let x = 1\nfn f() { return x }",
+                ResolveErrorKind::Captured,
+                at(26, 1),
+            ),
+            (
+                "fn f() { }\nf = 1",
+                ResolveErrorKind::NotAssignable,
+                at(11, 1),
+            ),
+            ("break", ResolveErrorKind::OutsideLoop, at(0, 5)),
+            // a function starts outside of the loops around it
+            (
+                "loop { fn g() { continue } }",
+                ResolveErrorKind::OutsideLoop,
+                at(16, 8),
+            ),
+            (
+                "a: loop { break b }",
+                ResolveErrorKind::UndefinedLabel,
+                at(16, 1),
+            ),
+            (
+                "len(1, 2)",
+                ResolveErrorKind::BuiltinArgs { expected: 1 },
+                at(0, 9),
+            ),
+        ];
+        for (src, kind, span) in cases {
+            assert_eq!(errors(src), [(kind, span)], "{}", src);
+        }
+        // every error is reported, not just the first
+        assert_eq!(errors("a\nbreak\nb").len(), 3);
+    }
+
+    #[test]
+    fn labeled_loops() {
+        let src =
+            "let n = 0\nouter: loop {\n  loop { n += 1; if n > 2 { break outer } }\n}\nreturn n";
+        assert_eq!(run(src), "3");
+    }
+
+    #[test]
+    fn compound_assignments_compute_their_place_once() {
+        let src = r#"fn f(calls) { calls[0] += 1; return 0 }
+let calls = b"\x00"
+let a = b"\x01"
+a[f(calls)] += 1
+return calls[0] * 10 + a[0]"#;
+        assert_eq!(run(src), "12");
+        let src = "record P { x }
+var n = 0
+fn g(p) { n += 1; return p }
+let p = P(1)
+g(p).x += 1
+return (n, p)";
+        assert_eq!(run(src), "(1, P { x: 2 })");
+    }
+
+    #[test]
+    fn closures_share_cells_after_their_function_returns() {
+        let src = "fn counter() {
+  let n = 0
+  fn inc() { n += 1 }
+  let get = fn() { return n }
+  inc()
+  return fn() { inc(); return get() }
+}
+let c = counter()
+let other = counter()
+c(); other()
+return c()";
+        // each call of counter makes a cell of its own
+        assert_eq!(run(src), "3");
+        // a closure inside a closure captures through the one around it
+        let src = "fn outer(x) {
+  return fn() { return fn() { x += 1; return x } }
+}
+let f = outer(40)()
+f()
+return f()";
+        assert_eq!(run(src), "42");
+    }
+
+    #[test]
+    fn closures_of_one_function_are_not_identical() {
+        let src = "fn mk(x) { return fn() { return x } }
+let a = mk(1)
+return (a === mk(2)) * 100 + (a === a) * 10 + (mk === mk)";
+        assert_eq!(run(src), "11");
+    }
+
+    #[test]
+    fn consts_and_vars() {
+        let src = "const step = 2
+var total = 0
+fn add() { total += step }
+add(); add()
+return total";
+        assert_eq!(run(src), "4");
+        let at = |start, len| Span { start, len };
+        assert_eq!(
+            errors("const a = 1\nfn f() { a = 2 }"),
+            [(ResolveErrorKind::NotAssignable, at(21, 1))]
+        );
+        assert_eq!(
+            errors("fn f() { var x }"),
+            [(ResolveErrorKind::NotTopLevel, at(9, 5))]
+        );
+        assert_eq!(
+            errors("if true { const x = 1 }"),
+            [(ResolveErrorKind::NotTopLevel, at(10, 11))]
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        assert_eq!(
+            run("let [a, ..rest, b] = [1, 2, 3, 4]\nreturn (a, rest, b)"),
+            "(1, [2, 3], 4)"
+        );
+        assert_eq!(
+            run("let (a, _) = (1, 2)\nlet b = 3\n(a, b) = (b, a)\nreturn [a, b]"),
+            "[3, 1]"
+        );
+    }
+
+    #[test]
+    fn for_loops() {
+        let src = "let total = 0
+outer: for x in [1, 2, 3] {
+  for y in (10, 20) {
+    if x == 2 { continue outer }
+    if y == 20 { break }
+    total += x * y
+  }
+}
+return total";
+        assert_eq!(run(src), "40");
+        // a table is visited in key order
+        let src = "let t = table([(3, 30), (1, 10)])
+t[2] = 20
+let out = \"\"
+for (k, v) in t { out = \"{out}{k}={v} \" }
+return out";
+        assert_eq!(run(src), r#""1=10 2=20 3=30 ""#);
+        assert_eq!(
+            run("let s = \"\"\nfor c in \"hé!\" { s = s + c + \".\" }\nreturn s"),
+            r#""h.é.!.""#
+        );
+    }
+
+    #[test]
+    fn interpolated_strings_join_their_parts() {
+        assert_eq!(run(r#"let x = 41; return "x: {x + 1}!""#), r#""x: 42!""#);
+        assert_eq!(
+            run(r#"return "{1.5} {none} {[1, "a"]}""#),
+            r#""1.5 none [1, \"a\"]""#
+        );
+        assert_eq!(run(r#"return "a{"b{1}c"}d""#), r#""ab1cd""#);
+        assert_eq!(run(r#"return "{"only"}""#), r#""only""#);
+    }
+
+    #[test]
+    fn slices_compute_their_seq_once() {
+        let src = r#"var n = 0
+fn g() { n += 1; return "abc" }
+let s = g()[1..]
+return (n, s, [1, 2, 3][..])"#;
+        assert_eq!(run(src), r#"(1, "bc", [1, 2, 3])"#);
+    }
+
+    #[test]
+    fn record_fields() {
+        // f doesn't know the type of its argument, so it looks x up by name
+        let src = "record Point { x, y }
+record Named { name, x }
+fn f(r) { return r.x }
+let p = Point(1, 2)
+p.y += 10
+return (f(p), f(Named(\"n\", 3)), p.y, p)";
+        assert_eq!(run(src), "(1, 3, 12, Point { x: 1, y: 12 })");
+        assert_eq!(
+            errors("record Point { x, y }\nlet p = Point(1, 2)\nreturn p.z"),
+            [(ResolveErrorKind::UndefinedField, Span { start: 51, len: 1 })]
+        );
+        assert_eq!(
+            errors("record Point { x, y }\nlet p = Point(1, 2)\np.z += 1"),
+            [(ResolveErrorKind::UndefinedField, Span { start: 44, len: 1 })]
+        );
+    }
+
+    #[test]
+    fn equal_literals_and_field_names_share_an_item() {
+        // main, f, "x", "y" and b"z"
+        let src = r#"fn f(r) { return (r.x, r.x, "x", "y", "y", b"z", b"z") }"#;
+        let module = resolve_src(src).expect("the source should resolve");
+        assert_eq!(module.items.len(), 5);
+        let src = r#"record P { x }
+fn f(r) { return (r.x, "x") }
+return (f(P(1)), "{1}x", b"x")"#;
+        assert_eq!(run(src), r#"((1, "x"), "1x", b"x")"#);
+    }
+}